@@ -0,0 +1,648 @@
+use serenity::all::{
+    CreateActionRow, CreateButton, CreateEmbed, EditGuild, EditMember, GuildId, Mentionable,
+    Timestamp, UserId, VerificationLevel,
+};
+use silverpelt::lockdowns::LockdownData;
+use std::time::Duration;
+
+use crate::{
+    bot::{
+        lockdowns::{record_snapshot, record_snapshot_after_remove},
+        sandwich_config,
+    },
+    botlib::{
+        durationstring::parse_duration_string, lockdown_snapshots::SnapshotKind, text::truncate,
+    },
+    Context, Error,
+};
+
+/// Guild feature set by Discord when invites are paused
+const INVITES_DISABLED: &str = "INVITES_DISABLED";
+
+#[derive(poise::ChoiceParameter)]
+enum PanicVerificationLevel {
+    #[name = "medium"]
+    Medium,
+    #[name = "high"]
+    High,
+    #[name = "highest"]
+    Highest,
+}
+
+impl From<PanicVerificationLevel> for VerificationLevel {
+    fn from(level: PanicVerificationLevel) -> Self {
+        match level {
+            PanicVerificationLevel::Medium => VerificationLevel::Medium,
+            PanicVerificationLevel::High => VerificationLevel::High,
+            PanicVerificationLevel::Highest => VerificationLevel::Higher,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PanicState {
+    lockdown_id: Option<uuid::Uuid>,
+    previous_verification_level: i32,
+    previous_invites_disabled: bool,
+    timed_out_users: Vec<String>,
+}
+
+/// Returns a copy of the guilds features with invites paused or unpaused
+fn with_invites_disabled(features: &[String], disabled: bool) -> Vec<String> {
+    let mut features = features
+        .iter()
+        .filter(|f| f.as_str() != INVITES_DISABLED)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if disabled {
+        features.push(INVITES_DISABLED.to_string());
+    }
+
+    features
+}
+
+/// Returns whether the guild is currently in panic mode
+async fn in_panic(pool: &sqlx::PgPool, guild_id: GuildId) -> Result<bool, Error> {
+    Ok(
+        sqlx::query("SELECT guild_id FROM antiraid__panic_states WHERE guild_id = $1")
+            .bind(guild_id.to_string())
+            .fetch_optional(pool)
+            .await?
+            .is_some(),
+    )
+}
+
+/// Emergency raid response
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("antiraid_panic", "antiraid_calm")
+)]
+pub async fn antiraid(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Locks down the server, pauses invites and raises the verification level in one step
+#[poise::command(slash_command, guild_only, rename = "panic")]
+pub async fn antiraid_panic(
+    ctx: Context<'_>,
+    #[description = "The reason for the panic"]
+    #[max_length = 384]
+    reason: String,
+    #[description = "Verification level to raise the server to. Defaults to high"]
+    verification_level: Option<PanicVerificationLevel>,
+    #[description = "Time out members who joined within this many minutes"]
+    recent_joins_minutes: Option<u32>,
+    #[description = "How long to time out recent joins for. Defaults to 1 hour"]
+    timeout_duration: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "antiraid.panic".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    if in_panic(&data.pool, guild_id).await? {
+        return Err("This server is already in panic mode. Use `/antiraid calm` first".into());
+    }
+
+    let verification_level: VerificationLevel = verification_level
+        .unwrap_or(PanicVerificationLevel::High)
+        .into();
+
+    let timeout_secs = {
+        let (dur, unit) = parse_duration_string(timeout_duration.as_deref().unwrap_or("1h"))?;
+        dur * unit.to_seconds()
+    };
+
+    // Ensure less than 28 days
+    if timeout_secs > 2419200 {
+        return Err("Timeout duration must be less than 28 days (2419200 seconds)".into());
+    }
+
+    let mut steps = vec![
+        "Start a quick server lockdown".to_string(),
+        "Pause invites".to_string(),
+        format!("Raise the verification level to `{:?}`", verification_level),
+    ];
+
+    if let Some(minutes) = recent_joins_minutes {
+        steps.push(format!(
+            "Time out members who joined in the last {} minutes",
+            minutes
+        ));
+    }
+
+    let confirm = ctx
+        .send(
+            poise::CreateReply::new()
+                .embed(
+                    CreateEmbed::new()
+                        .title("Panic?")
+                        .description(format!(
+                            "This will:\n{}\n\nUse `/antiraid calm` to undo these changes.",
+                            steps
+                                .iter()
+                                .map(|s| format!("- {}", s))
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        ))
+                        .color(serenity::all::Colour::RED),
+                )
+                .components(vec![CreateActionRow::buttons(vec![
+                    CreateButton::new("yes")
+                        .label("Panic")
+                        .style(serenity::all::ButtonStyle::Danger),
+                    CreateButton::new("no")
+                        .label("Cancel")
+                        .style(serenity::all::ButtonStyle::Secondary),
+                ])]),
+        )
+        .await?
+        .into_message()
+        .await?;
+
+    let Some(confirm) = confirm
+        .id
+        .await_component_interaction(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60))
+        .await
+    else {
+        return Err("No response".into());
+    };
+
+    if confirm.data.custom_id != "yes" {
+        confirm
+            .create_response(
+                ctx.http(),
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new()
+                        .content("Cancelled successfully!"),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    confirm.defer(ctx.http()).await?;
+
+    let sctx = ctx.serenity_context();
+    let guild = sandwich_driver::guild(
+        &sctx.cache,
+        &sctx.http,
+        &data.reqwest,
+        guild_id,
+        &sandwich_config(),
+    )
+    .await?;
+
+    let previous_verification_level = guild.verification_level;
+    let features = guild
+        .features
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>();
+    let previous_invites_disabled = features.iter().any(|f| f == INVITES_DISABLED);
+
+    // Record the panic state before changing anything so that `/antiraid calm` can undo whatever
+    // succeeds even if a later step fails. The primary key also stops two panics running at once
+    let claimed = sqlx::query(
+        "INSERT INTO antiraid__panic_states (guild_id, previous_verification_level, previous_invites_disabled, created_by) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id) DO NOTHING",
+    )
+    .bind(guild_id.to_string())
+    .bind(u8::from(previous_verification_level) as i32)
+    .bind(previous_invites_disabled)
+    .bind(ctx.author().id.to_string())
+    .execute(&data.pool)
+    .await?;
+
+    if claimed.rows_affected() == 0 {
+        return Err("This server is already in panic mode. Use `/antiraid calm` first".into());
+    }
+
+    let mut status = Vec::new();
+
+    // Apply the QSL
+    let mut lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            sctx.cache.clone(),
+            sctx.http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+    let existing_ids = lockdowns
+        .lockdowns()
+        .iter()
+        .map(|l| l.id)
+        .collect::<Vec<_>>();
+
+    if existing_ids.is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
+    match lockdowns
+        .apply(Box::new(lockdowns::qsl::QuickServerLockdown {}), &reason)
+        .await
+    {
+        Ok(_) => {
+            record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+            status.push(":white_check_mark: Quick server lockdown started".to_string());
+
+            let lockdown_id = lockdowns
+                .lockdowns()
+                .iter()
+                .find(|l| !existing_ids.contains(&l.id))
+                .map(|l| l.id);
+
+            if let Err(e) = sqlx::query(
+                "UPDATE antiraid__panic_states SET lockdown_id = $1 WHERE guild_id = $2",
+            )
+            .bind(lockdown_id)
+            .bind(guild_id.to_string())
+            .execute(&data.pool)
+            .await
+            {
+                log::error!("Failed to record panic lockdown: {}", e);
+                status.push(format!(
+                    ":x: Failed to record the lockdown, it must be removed manually with `/lockdowns remove`: {}",
+                    e
+                ));
+            }
+        }
+        Err(e) => status.push(format!(":x: Failed to start lockdown: {}", e)),
+    }
+
+    // Pause invites and raise the verification level
+    let audit_log_reason = format!("Panic by {}: {}", ctx.author().id, reason);
+
+    match guild_id
+        .edit(
+            ctx.http(),
+            EditGuild::new()
+                .features(with_invites_disabled(&features, true))
+                .audit_log_reason(&audit_log_reason),
+        )
+        .await
+    {
+        Ok(_) => status.push(":white_check_mark: Invites paused".to_string()),
+        Err(e) => status.push(format!(":x: Failed to pause invites: {}", e)),
+    }
+
+    if u8::from(previous_verification_level) >= u8::from(verification_level) {
+        status.push(format!(
+            ":white_check_mark: Verification level is already `{:?}`",
+            previous_verification_level
+        ));
+    } else {
+        match guild_id
+            .edit(
+                ctx.http(),
+                EditGuild::new()
+                    .verification_level(verification_level)
+                    .audit_log_reason(&audit_log_reason),
+            )
+            .await
+        {
+            Ok(_) => status.push(format!(
+                ":white_check_mark: Verification level raised to `{:?}`",
+                verification_level
+            )),
+            Err(e) => status.push(format!(":x: Failed to raise verification level: {}", e)),
+        }
+    }
+
+    // Time out recent joins
+    let mut timed_out = 0;
+
+    if let Some(minutes) = recent_joins_minutes {
+        let cutoff = Timestamp::now().unix_timestamp() - (minutes as i64 * 60);
+        let until = Timestamp::from_unix_timestamp(
+            Timestamp::now().unix_timestamp() + timeout_secs as i64,
+        )?;
+
+        let mut after = None;
+        let mut failed = 0;
+        let mut unrecorded = Vec::new();
+
+        loop {
+            let members = match ctx
+                .http()
+                .get_guild_members(guild_id, Some(1000), after)
+                .await
+            {
+                Ok(members) => members,
+                Err(e) => {
+                    status.push(format!(":x: Failed to fetch members: {}", e));
+                    break;
+                }
+            };
+
+            let Some(last) = members.last() else {
+                break;
+            };
+
+            after = Some(last.user.id.get());
+
+            for member in members.iter() {
+                let Some(joined_at) = member.joined_at else {
+                    continue;
+                };
+
+                if joined_at.unix_timestamp() < cutoff
+                    || member.user.bot()
+                    || member.user.id == guild.owner_id
+                {
+                    continue;
+                }
+
+                match guild_id
+                    .edit_member(
+                        ctx.http(),
+                        member.user.id,
+                        EditMember::new()
+                            .disable_communication_until(until)
+                            .audit_log_reason(&audit_log_reason),
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        if let Err(e) = sqlx::query(
+                            "UPDATE antiraid__panic_states SET timed_out_users = array_append(timed_out_users, $1) WHERE guild_id = $2",
+                        )
+                        .bind(member.user.id.to_string())
+                        .bind(guild_id.to_string())
+                        .execute(&data.pool)
+                        .await
+                        {
+                            log::error!("Failed to record timed out member {}: {}", member.user.id, e);
+                            unrecorded.push(member.user.id);
+                        }
+
+                        timed_out += 1;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to time out member {}: {}", member.user.id, e);
+                        failed += 1;
+                    }
+                }
+            }
+
+            if members.len() < 1000 {
+                break;
+            }
+        }
+
+        status.push(format!(
+            ":white_check_mark: Timed out {}/{} recent joins",
+            timed_out,
+            timed_out + failed
+        ));
+
+        if !unrecorded.is_empty() {
+            status.push(truncate(
+                &format!(
+                    ":warning: {} timed out members could not be recorded and will not be released by `/antiraid calm`: {}",
+                    unrecorded.len(),
+                    unrecorded
+                        .iter()
+                        .map(|u| u.mention().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                1024,
+            ));
+        }
+    }
+
+    confirm
+        .create_followup(
+            ctx.http(),
+            serenity::all::CreateInteractionResponseFollowup::new().embed(
+                CreateEmbed::new()
+                    .title("Panic Mode Enabled")
+                    .description(status.join("\n"))
+                    .color(serenity::all::Colour::RED),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Undoes the changes made by `/antiraid panic`
+#[poise::command(slash_command, guild_only, rename = "calm")]
+pub async fn antiraid_calm(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "antiraid.calm".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    let state: Option<PanicState> = sqlx::query_as(
+        "SELECT lockdown_id, previous_verification_level, previous_invites_disabled, timed_out_users FROM antiraid__panic_states WHERE guild_id = $1",
+    )
+    .bind(guild_id.to_string())
+    .fetch_optional(&data.pool)
+    .await?;
+
+    let Some(state) = state else {
+        return Err("This server is not in panic mode".into());
+    };
+
+    ctx.defer().await?;
+
+    let sctx = ctx.serenity_context();
+    let mut status = Vec::new();
+    let mut failed = false;
+    let audit_log_reason = format!("Calm by {}", ctx.author().id);
+
+    // Remove the lockdown if it still exists
+    let mut remaining_lockdown = None;
+
+    if let Some(lockdown_id) = state.lockdown_id {
+        let mut lockdowns = lockdowns::LockdownSet::guild(
+            guild_id,
+            LockdownData::new(
+                sctx.cache.clone(),
+                sctx.http.clone(),
+                data.pool.clone(),
+                data.reqwest.clone(),
+                sandwich_config(),
+            ),
+        )
+        .await
+        .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+        if lockdowns.lockdowns().iter().any(|l| l.id == lockdown_id) {
+            match lockdowns.remove(lockdown_id).await {
+                Ok(_) => {
+                    record_snapshot_after_remove(&ctx, guild_id, lockdowns.lockdowns().is_empty())
+                        .await;
+                    status.push(":white_check_mark: Lockdown removed".to_string());
+                }
+                Err(e) => {
+                    status.push(format!(":x: Failed to remove lockdown: {}", e));
+                    remaining_lockdown = Some(lockdown_id);
+                    failed = true;
+                }
+            }
+        } else {
+            status.push(":white_check_mark: Lockdown was already removed".to_string());
+        }
+    }
+
+    // Restore invites and verification level
+    let guild = sandwich_driver::guild(
+        &sctx.cache,
+        &sctx.http,
+        &data.reqwest,
+        guild_id,
+        &sandwich_config(),
+    )
+    .await?;
+
+    let features = guild
+        .features
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>();
+
+    match guild_id
+        .edit(
+            ctx.http(),
+            EditGuild::new()
+                .features(with_invites_disabled(
+                    &features,
+                    state.previous_invites_disabled,
+                ))
+                .verification_level(VerificationLevel::from(
+                    state.previous_verification_level as u8,
+                ))
+                .audit_log_reason(&audit_log_reason),
+        )
+        .await
+    {
+        Ok(_) => {
+            status.push(":white_check_mark: Invites and verification level restored".to_string())
+        }
+        Err(e) => {
+            status.push(format!(
+                ":x: Failed to restore invites and verification level: {}",
+                e
+            ));
+            failed = true;
+        }
+    }
+
+    // Lift timeouts, keeping the users whose timeouts could not be lifted
+    let mut remaining_users = Vec::new();
+
+    if !state.timed_out_users.is_empty() {
+        let mut lifted = 0;
+
+        for user_id in state.timed_out_users.iter() {
+            let Ok(user_id) = user_id.parse::<UserId>() else {
+                continue;
+            };
+
+            match guild_id
+                .edit_member(
+                    ctx.http(),
+                    user_id,
+                    EditMember::new()
+                        .enable_communication()
+                        .audit_log_reason(&audit_log_reason),
+                )
+                .await
+            {
+                Ok(_) => lifted += 1,
+                Err(e) => {
+                    log::error!("Failed to lift timeout of {}: {}", user_id, e);
+                    remaining_users.push(user_id.to_string());
+                }
+            }
+        }
+
+        status.push(format!(
+            ":white_check_mark: Lifted {}/{} timeouts",
+            lifted,
+            state.timed_out_users.len()
+        ));
+
+        if !remaining_users.is_empty() {
+            failed = true;
+        }
+    }
+
+    // Keep the panic state on failure so that calm can be retried for whatever was not undone
+    if failed {
+        sqlx::query(
+            "UPDATE antiraid__panic_states SET lockdown_id = $1, timed_out_users = $2 WHERE guild_id = $3",
+        )
+        .bind(remaining_lockdown)
+        .bind(remaining_users)
+        .bind(guild_id.to_string())
+        .execute(&data.pool)
+        .await?;
+
+        status.push(
+            "\nSome changes could not be undone. Run `/antiraid calm` again to retry them."
+                .to_string(),
+        );
+    } else {
+        sqlx::query("DELETE FROM antiraid__panic_states WHERE guild_id = $1")
+            .bind(guild_id.to_string())
+            .execute(&data.pool)
+            .await?;
+    }
+
+    ctx.send(
+        poise::CreateReply::new().embed(
+            CreateEmbed::new()
+                .title(if failed {
+                    "Panic Mode Partially Disabled"
+                } else {
+                    "Panic Mode Disabled"
+                })
+                .description(status.join("\n"))
+                .color(if failed {
+                    serenity::all::Colour::RED
+                } else {
+                    serenity::all::Colour::DARK_GREEN
+                }),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
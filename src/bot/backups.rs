@@ -1,13 +1,28 @@
+use crate::bot::lockdowns::{create_lockdown_mode, record_snapshot};
+use crate::bot::{sandwich_config, template_dispatch_data};
+use crate::botlib::antiraidconfig::{self, ConflictMode};
+use crate::botlib::backupdiff::BackupDiff;
+use crate::botlib::backupfile::{self, BackupGuild};
+use crate::botlib::backupusage;
+use crate::botlib::durationstring::parse_duration_string;
+use crate::botlib::lockdown_snapshots::SnapshotKind;
 use crate::botlib::numericlistparser::{parse_numeric_list, REPLACE_CHANNEL};
+use crate::botlib::restoreplan::{RestorePlan, RestorePlanOptions};
 use crate::botlib::specialchannelallocs::create_special_allocation_from_str;
+use crate::botlib::text::truncate;
 use crate::config::CONFIG;
 use crate::Context;
+use antiraid_types::ar_event::AntiraidEvent;
 use futures_util::StreamExt;
 use jobserver::embed::{embed as embed_job, get_icon_of_state};
 use serenity::all::{ChannelId, CreateEmbed, EditMessage};
 use serenity::small_fixed_array::TruncatingInto;
+use silverpelt::ar_event::AntiraidEventOperations;
+use silverpelt::lockdowns::LockdownData;
+use silverpelt::objectstore::guild_bucket;
 use silverpelt::Error;
 use sqlx::types::uuid::Uuid;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::time::Duration;
 
@@ -38,12 +53,90 @@ type BackupRestoreOpts struct {
 }
 */
 
+/// Creates the options for a guild_create_backup job, applying defaults where needed
+#[allow(clippy::too_many_arguments)]
+fn create_backup_opts_serde(
+    messages: Option<bool>,
+    channels: &Option<String>,
+    attachments: Option<bool>,
+    backup_guild_assets: Option<String>,
+    rollover_leftovers: Option<bool>,
+    ignore_message_backup_errors: Option<bool>,
+    max_messages: Option<i32>,
+    per_channel: Option<i32>,
+    special_allocations: Option<String>,
+    password: Option<String>,
+) -> Result<serde_json::Value, Error> {
+    let messages = messages.unwrap_or(false);
+    let attachments = attachments.unwrap_or(false);
+    let backup_guild_assets = backup_guild_assets.unwrap_or_default();
+    let rollover_leftovers = rollover_leftovers.unwrap_or(true);
+    let ignore_message_backup_errors = ignore_message_backup_errors.unwrap_or(false);
+    let max_messages = max_messages.unwrap_or(500);
+    let per_channel = per_channel.unwrap_or(100);
+    let special_allocations = special_allocations.unwrap_or_default();
+    let password = password.unwrap_or_default();
+    if !messages && attachments {
+        return Err("You must backup messages to backup attachments".into());
+    }
+
+    let backup_guild_assets = {
+        let split = backup_guild_assets.split(',').collect::<Vec<&str>>();
+
+        if !split.is_empty() {
+            split
+                .iter()
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<&str>>()
+        } else {
+            vec!["icon", "splash", "banner"]
+        }
+    };
+
+    let channels: Vec<ChannelId> = if let Some(channels) = channels {
+        parse_numeric_list(channels, &REPLACE_CHANNEL)?
+    } else {
+        vec![]
+    };
+
+    let special_allocations = create_special_allocation_from_str(&special_allocations)?;
+
+    Ok(serde_json::json!({
+        "Options": {
+            "Channels": channels,
+            "PerChannel": per_channel,
+            "MaxMessages": max_messages,
+            "BackupMessages": messages,
+            "BackupAttachments": attachments,
+            "BackupGuildAssets": backup_guild_assets,
+            "IgnoreMessageBackupErrors": ignore_message_backup_errors,
+            "RolloverLeftovers": rollover_leftovers,
+            "SpecialAllocations": special_allocations,
+            "Encrypt": password
+        }
+    }))
+}
+
 /// Create, load and get info on backups of your server!
 #[poise::command(
     slash_command,
     guild_only,
     user_cooldown = "5",
-    subcommands("backups_create", "backups_list", "backups_delete", "backups_restore")
+    subcommands(
+        "backups_create",
+        "backups_list",
+        "backups_delete",
+        "backups_restore",
+        "backups_schedule",
+        "backups_diff",
+        "backups_restore_messages",
+        "backups_pin",
+        "backups_unpin",
+        "backups_verify",
+        "backups_usage",
+        "backups_quota"
+    )
 )]
 pub async fn backups(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -83,6 +176,14 @@ pub async fn backups_create(
 
     #[description = "Password to encrypt the backup with. If not provided, the backup will not be encrypted"]
     password: Option<String>,
+
+    #[description = "A short label to identify the backup by, e.g. 'pre-event-setup'"]
+    label: Option<String>,
+
+    #[description = "A longer note describing the backup"] note: Option<String>,
+
+    #[description = "Also back up role permissions, templates, stings, punishments and active lockdowns"]
+    include_antiraid_config: Option<bool>,
 ) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild_id() else {
         return Err("This command can only be used in a guild".into());
@@ -99,40 +200,37 @@ pub async fn backups_create(
     )
     .await?;
 
-    let messages = messages.unwrap_or(false);
-    let attachments = attachments.unwrap_or(false);
-    let backup_guild_assets = backup_guild_assets.unwrap_or_default();
-    let rollover_leftovers = rollover_leftovers.unwrap_or(true);
-    let ignore_message_backup_errors = ignore_message_backup_errors.unwrap_or(false);
-    let max_messages = max_messages.unwrap_or(500);
-    let per_channel = per_channel.unwrap_or(100);
-    let special_allocations = special_allocations.unwrap_or_default();
-    let password = password.unwrap_or_default();
-    if !messages && attachments {
-        return Err("You must backup messages to backup attachments".into());
-    }
-
-    let backup_guild_assets = {
-        let split = backup_guild_assets.split(',').collect::<Vec<&str>>();
-
-        if !split.is_empty() {
-            split
-                .iter()
-                .map(|v| v.trim())
-                .filter(|v| !v.is_empty())
-                .collect::<Vec<&str>>()
-        } else {
-            vec!["icon", "splash", "banner"]
-        }
-    };
-
-    let channels: Vec<ChannelId> = if let Some(channels) = channels {
-        parse_numeric_list(&channels, &REPLACE_CHANNEL)?
+    let backup_args = create_backup_opts_serde(
+        messages,
+        &channels,
+        attachments,
+        backup_guild_assets,
+        rollover_leftovers,
+        ignore_message_backup_errors,
+        max_messages,
+        per_channel,
+        special_allocations,
+        password.clone(),
+    )?;
+
+    // Exported before the backup starts so that both reflect the same point in time
+    let antiraid_config = if include_antiraid_config.unwrap_or(false) {
+        Some(antiraidconfig::export(&ctx.data().pool, guild_id).await?)
     } else {
-        vec![]
+        None
     };
 
-    let special_allocations = create_special_allocation_from_str(&special_allocations)?;
+    ctx.defer().await?;
+
+    let usage = backupusage::guild_usage(ctx.data(), guild_id).await?;
+
+    if usage.is_over_quota() {
+        return Err(format!(
+            "This server has used its backup storage quota ({}). Delete some backups with `/backups delete` before creating new ones",
+            usage.summary()
+        )
+        .into());
+    }
 
     let base_message = ctx
         .send(
@@ -144,22 +242,6 @@ pub async fn backups_create(
         )
         .await?;
 
-    // Create backup
-    let backup_args = serde_json::json!({
-        "Options": {
-            "Channels": channels,
-            "PerChannel": per_channel,
-            "MaxMessages": max_messages,
-            "BackupMessages": messages,
-            "BackupAttachments": attachments,
-            "BackupGuildAssets": backup_guild_assets,
-            "IgnoreMessageBackupErrors": ignore_message_backup_errors,
-            "RolloverLeftovers": rollover_leftovers,
-            "SpecialAllocations": special_allocations,
-            "Encrypt": password
-        }
-    });
-
     let data = ctx.data();
 
     // Make request to jobserver
@@ -179,6 +261,30 @@ pub async fn backups_create(
     .await?
     .id;
 
+    // Every backup gets a metadata row so that its checksum is recorded once the job completes
+    sqlx::query(
+        "INSERT INTO backups__job_metadata (job_id, guild_id, label, note) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(backup_id.parse::<Uuid>()?)
+    .bind(guild_id.to_string())
+    .bind(label)
+    .bind(note)
+    .execute(&data.pool)
+    .await?;
+
+    if let Some(ref config) = antiraid_config {
+        let job_id = backup_id.parse::<Uuid>()?;
+
+        antiraidconfig::save(data, guild_id, job_id, config, password.as_deref()).await?;
+
+        sqlx::query(
+            "UPDATE backups__job_metadata SET has_antiraid_config = true WHERE job_id = $1",
+        )
+        .bind(job_id)
+        .execute(&data.pool)
+        .await?;
+    }
+
     base_message
         .edit(
             ctx,
@@ -228,9 +334,334 @@ pub async fn backups_create(
     Ok(())
 }
 
+/// Shows how much backup storage the server is using
+#[poise::command(slash_command, guild_only, user_cooldown = "10", rename = "usage")]
+pub async fn backups_usage(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.list".into(),
+    )
+    .await?;
+
+    ctx.defer().await?;
+
+    let usage = backupusage::guild_usage(ctx.data(), guild_id).await?;
+
+    let mut largest = usage
+        .backups
+        .iter()
+        .take(10)
+        .map(|(id, size)| format!("`{}`: {}", id, backupusage::format_bytes(*size)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if largest.is_empty() {
+        largest = "No backups".to_string();
+    }
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("Backup Storage Usage")
+                .description(usage.summary())
+                .field("Backups", usage.backups.len().to_string(), true)
+                .field("Largest Backups", largest, false)
+                .color(if usage.is_over_quota() {
+                    serenity::all::Colour::RED
+                } else {
+                    serenity::all::Colour::DARK_GREEN
+                }),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sets or resets the backup storage quota of a server (AntiRaid staff only)
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "quota")]
+pub async fn backups_quota(
+    ctx: Context<'_>,
+    #[description = "The server to change the quota of, defaults to this server"] guild_id: Option<
+        String,
+    >,
+    #[description = "The new quota in megabytes, leave empty to reset to the default"]
+    quota_mb: Option<u64>,
+) -> Result<(), Error> {
+    if !CONFIG.discord_auth.root_users.contains(&ctx.author().id) {
+        return Err("Only AntiRaid staff can change backup quotas".into());
+    }
+
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id
+            .parse::<serenity::all::GuildId>()
+            .map_err(|_| "Invalid server ID")?,
+        None => ctx
+            .guild_id()
+            .ok_or("This command can only be used in a guild")?,
+    };
+
+    backupusage::set_guild_quota(&ctx.data().pool, guild_id, quota_mb).await?;
+
+    let quota = backupusage::guild_quota(&ctx.data().pool, guild_id).await?;
+
+    ctx.say(format!(
+        "Backup quota of `{}` is now {}{}",
+        guild_id,
+        backupusage::format_bytes(quota),
+        if quota_mb.is_none() { " (default)" } else { "" }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Checks that a backup is intact and can be decrypted and read
+#[poise::command(slash_command, guild_only, user_cooldown = "10", rename = "verify")]
+pub async fn backups_verify(
+    ctx: Context<'_>,
+    #[description = "The job id of the backup to verify"] id: String,
+    #[description = "Password the backup was encrypted with, if any"] password: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.list".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    let job = jobserver::Job::from_id(id.trim().parse::<Uuid>()?, &data.pool)
+        .await
+        .map_err(|e| format!("Failed to get backup job: {}", e))?;
+
+    if job.guild_id != guild_id {
+        return Err("Backup is not owned by this server".into());
+    }
+
+    if job.name != "guild_create_backup" {
+        return Err("Job is not a backup".into());
+    }
+
+    if job.state != "completed" {
+        return Err(format!("Backup has not completed (state: {})", job.state).into());
+    }
+
+    ctx.defer().await?;
+
+    let mut status = Vec::new();
+    let mut ok = true;
+
+    let contents = match backupfile::download(data, &job).await {
+        Ok(contents) => {
+            status.push(format!(
+                ":white_check_mark: Downloaded backup ({} bytes)",
+                contents.len()
+            ));
+            contents
+        }
+        Err(e) => {
+            return Err(format!("Failed to download backup: {}", e).into());
+        }
+    };
+
+    #[derive(sqlx::FromRow)]
+    struct ChecksumRecord {
+        checksum: Option<String>,
+    }
+
+    let rec: Option<ChecksumRecord> =
+        sqlx::query_as("SELECT checksum FROM backups__job_metadata WHERE job_id = $1")
+            .bind(job.id)
+            .fetch_optional(&data.pool)
+            .await?;
+
+    let actual = backupfile::checksum(&contents);
+    match rec.and_then(|r| r.checksum) {
+        Some(expected) if expected == actual => {
+            status.push(":white_check_mark: Checksum matches".to_string());
+        }
+        Some(expected) => {
+            ok = false;
+            status.push(format!(
+                ":x: Checksum mismatch, the backup may be corrupt\nExpected: `{}`\nFound: `{}`",
+                expected, actual
+            ));
+        }
+        None => {
+            // The downloaded file is not trusted as the reference, so nothing is recorded here
+            status.push(format!(
+                ":yellow_circle: No checksum was recorded when this backup completed\nFound: `{}`",
+                actual
+            ));
+        }
+    }
+
+    match backupfile::read_sections(contents, password.as_deref()) {
+        Ok(sections) => {
+            status.push(format!(
+                ":white_check_mark: Backup decrypted and read ({} sections)",
+                sections.len()
+            ));
+
+            match BackupGuild::from_sections(&sections) {
+                Ok(guild) => status.push(format!(
+                    ":white_check_mark: Server data parsed ({} roles, {} channels)",
+                    guild.roles.len(),
+                    guild.channels.len()
+                )),
+                Err(e) => {
+                    ok = false;
+                    status.push(format!(":x: Failed to parse server data: {}", e));
+                }
+            }
+        }
+        Err(e) => {
+            ok = false;
+            status.push(format!(":x: {}", e));
+        }
+    }
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title(if ok {
+                    "Backup Verified"
+                } else {
+                    "Backup Verification Failed"
+                })
+                .description(status.join("\n"))
+                .color(if ok {
+                    serenity::all::Colour::DARK_GREEN
+                } else {
+                    serenity::all::Colour::RED
+                }),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct BackupMetadata {
+    job_id: Uuid,
+    label: Option<String>,
+    note: Option<String>,
+    pinned: bool,
+    scheduled: bool,
+}
+
+/// Returns the labels, notes and pin state of all backups of a guild
+async fn fetch_backup_metadata(
+    pool: &sqlx::PgPool,
+    guild_id: serenity::all::GuildId,
+) -> Result<HashMap<Uuid, BackupMetadata>, Error> {
+    let rows: Vec<BackupMetadata> = sqlx::query_as(
+        "SELECT job_id, label, note, pinned, scheduled FROM backups__job_metadata WHERE guild_id = $1",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| (r.job_id, r)).collect())
+}
+
+/// Sets whether a backup is pinned, pinned backups are never automatically deleted
+async fn set_backup_pinned(ctx: Context<'_>, id: String, pinned: bool) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.pin".into(),
+    )
+    .await?;
+
+    let job = jobserver::Job::from_id(id.trim().parse::<Uuid>()?, &ctx.data().pool)
+        .await
+        .map_err(|e| format!("Failed to get backup job: {}", e))?;
+
+    if job.guild_id != guild_id {
+        return Err("Backup is not owned by this server".into());
+    }
+
+    if job.name != "guild_create_backup" {
+        return Err("Job is not a backup".into());
+    }
+
+    sqlx::query(
+        "INSERT INTO backups__job_metadata (job_id, guild_id, pinned) VALUES ($1, $2, $3) ON CONFLICT (job_id) DO UPDATE SET pinned = EXCLUDED.pinned",
+    )
+    .bind(job.id)
+    .bind(guild_id.to_string())
+    .bind(pinned)
+    .execute(&ctx.data().pool)
+    .await?;
+
+    if pinned {
+        ctx.say(format!(
+            ":pushpin: Backup `{}` pinned, it will never be automatically deleted",
+            job.id
+        ))
+        .await?;
+    } else {
+        ctx.say(format!("Backup `{}` unpinned", job.id)).await?;
+    }
+
+    Ok(())
+}
+
+/// Pins a backup so it is never automatically deleted
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "pin")]
+pub async fn backups_pin(
+    ctx: Context<'_>,
+    #[description = "The job id of the backup to pin"] id: String,
+) -> Result<(), Error> {
+    set_backup_pinned(ctx, id, true).await
+}
+
+/// Unpins a backup, allowing it to be deleted by the backup schedule's retention policy
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "unpin")]
+pub async fn backups_unpin(
+    ctx: Context<'_>,
+    #[description = "The job id of the backup to unpin"] id: String,
+) -> Result<(), Error> {
+    set_backup_pinned(ctx, id, false).await
+}
+
 /// Lists all currently made backups + download/delete them
 #[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "list")]
-pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
+pub async fn backups_list(
+    ctx: Context<'_>,
+    #[description = "Only show backups whose ID, label or note contains this text"] search: Option<
+        String,
+    >,
+) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild_id() else {
         return Err("This command can only be used in a guild".into());
     };
@@ -248,6 +679,8 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
 
     let data = ctx.data();
 
+    ctx.defer_ephemeral().await?;
+
     let mut backup_jobs =
         jobserver::Job::from_guild_and_name(guild_id, "guild_create_backup", &data.pool)
             .await
@@ -258,7 +691,48 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     }
 
-    fn create_embed_for_job<'a>(job: &jobserver::Job) -> serenity::all::CreateEmbed<'a> {
+    let metadata = fetch_backup_metadata(&data.pool, guild_id).await?;
+    // Storage usage is only informational here, so a failed lookup should not stop backups from being listed
+    let usage = match backupusage::guild_usage(data, guild_id).await {
+        Ok(usage) => usage.summary(),
+        Err(e) => {
+            log::warn!("Failed to get backup storage usage of {}: {}", guild_id, e);
+            "unavailable".to_string()
+        }
+    };
+
+    if let Some(search) = search {
+        let search = search.to_lowercase();
+
+        backup_jobs.retain(|job| {
+            if job.id.to_string().contains(&search) {
+                return true;
+            }
+
+            let Some(meta) = metadata.get(&job.id) else {
+                return false;
+            };
+
+            meta.label
+                .as_ref()
+                .is_some_and(|l| l.to_lowercase().contains(&search))
+                || meta
+                    .note
+                    .as_ref()
+                    .is_some_and(|n| n.to_lowercase().contains(&search))
+        });
+
+        if backup_jobs.is_empty() {
+            ctx.say("No backups match your search").await?;
+            return Ok(());
+        }
+    }
+
+    fn create_embed_for_job<'a>(
+        job: &jobserver::Job,
+        meta: Option<&BackupMetadata>,
+        usage: &str,
+    ) -> serenity::all::CreateEmbed<'a> {
         let mut initial_desc = format!(
             "ID: {}\nName: {}\nState: {}\n**Created At**: <t:{}:f> (<t:{}:R>)",
             job.id,
@@ -268,6 +742,22 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
             job.created_at.timestamp()
         );
 
+        if let Some(meta) = meta {
+            if let Some(ref label) = meta.label {
+                initial_desc += &format!("\n**Label**: {}", label);
+            }
+
+            if let Some(ref note) = meta.note {
+                initial_desc += &format!("\n**Note**: {}", note);
+            }
+
+            if meta.pinned {
+                initial_desc += "\n:pushpin: Pinned (never automatically deleted)";
+            } else if meta.scheduled {
+                initial_desc += "\n:clock3: Scheduled backup";
+            }
+        }
+
         let embed = poise::serenity_prelude::CreateEmbed::default().title(format!(
             "{} | Server Backup",
             get_icon_of_state(job.state.as_str())
@@ -281,19 +771,29 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
 
         embed
             .description(initial_desc)
+            .footer(serenity::all::CreateEmbedFooter::new(format!(
+                "Storage: {}",
+                usage
+            )))
             .color(poise::serenity_prelude::Colour::DARK_GREEN)
     }
 
     fn create_reply<'a>(
         index: usize,
         backup_jobs: &[jobserver::Job],
+        metadata: &HashMap<Uuid, BackupMetadata>,
+        usage: &str,
     ) -> Result<poise::CreateReply<'a>, Error> {
         if backup_jobs.is_empty() || index >= backup_jobs.len() {
             return Err("No backups found".into());
         }
 
         let cr = poise::CreateReply::default()
-            .embed(create_embed_for_job(&backup_jobs[index]))
+            .embed(create_embed_for_job(
+                &backup_jobs[index],
+                metadata.get(&backup_jobs[index].id),
+                usage,
+            ))
             .ephemeral(true)
             .components(vec![
                 serenity::all::CreateActionRow::Buttons(
@@ -347,7 +847,7 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
 
     let mut index = 0;
 
-    let cr = create_reply(index, &backup_jobs)?;
+    let cr = create_reply(index, &backup_jobs, &metadata, &usage)?;
 
     let msg = ctx.send(cr).await?.into_message().await?;
 
@@ -747,6 +1247,16 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
 
                         match job.delete(&data.pool, &data.object_store).await {
                             Ok(_) => {
+                                if let Err(e) =
+                                    backupfile::delete_metadata(data, guild_id, job.id).await
+                                {
+                                    log::error!(
+                                        "Failed to delete metadata of backup {}: {}",
+                                        job.id,
+                                        e
+                                    );
+                                }
+
                                 status.push(
                                     ":white_check_mark: Successfully deleted the backup"
                                         .to_string(),
@@ -802,7 +1312,7 @@ pub async fn backups_list(ctx: Context<'_>) -> Result<(), Error> {
             item.defer(&ctx.serenity_context().http).await?;
         }
 
-        let cr = create_reply(index, &backup_jobs)?;
+        let cr = create_reply(index, &backup_jobs, &metadata, &usage)?;
 
         item.edit_response(
             &ctx.serenity_context().http,
@@ -905,6 +1415,8 @@ pub async fn backups_delete(ctx: Context<'_>, id: String) -> Result<(), Error> {
             let data = &ctx.data();
             match job.delete(&data.pool, &data.object_store).await {
                 Ok(_) => {
+                    backupfile::delete_metadata(data, guild_id, job.id).await?;
+
                     status.push(":white_check_mark: Successfully deleted the backup".to_string());
                 }
                 Err(e) => {
@@ -940,6 +1452,356 @@ pub async fn backups_delete(ctx: Context<'_>, id: String) -> Result<(), Error> {
     Ok(())
 }
 
+/// Loads a guild from a backup job ID, or the live guild if `id` is `live`
+async fn load_backup_guild(
+    ctx: &Context<'_>,
+    guild_id: serenity::all::GuildId,
+    id: &str,
+    password: Option<&str>,
+) -> Result<BackupGuild, Error> {
+    if id.trim().eq_ignore_ascii_case("live") {
+        return BackupGuild::live(&ctx.serenity_context().http, guild_id).await;
+    }
+
+    let job = jobserver::Job::from_id(id.trim().parse::<Uuid>()?, &ctx.data().pool)
+        .await
+        .map_err(|e| format!("Failed to get backup {}: {}", id, e))?;
+
+    if job.guild_id != guild_id {
+        return Err(format!("Backup {} is not owned by this server", id).into());
+    }
+
+    if job.name != "guild_create_backup" {
+        return Err(format!("Job {} is not a backup", id).into());
+    }
+
+    let contents = backupfile::download(ctx.data(), &job).await?;
+
+    BackupGuild::from_backup(contents, password)
+}
+
+/// Shows the differences between two backups, or a backup and the server as it is now
+#[poise::command(slash_command, guild_only, user_cooldown = "10", rename = "diff")]
+pub async fn backups_diff(
+    ctx: Context<'_>,
+    #[description = "The job id of the older backup"] a: String,
+    #[description = "The job id of the newer backup, or 'live' for the server as it is now"]
+    b: String,
+    #[description = "Password to decrypt the first backup with"] password_a: Option<String>,
+    #[description = "Password to decrypt the second backup with"] password_b: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.list".into(),
+    )
+    .await?;
+
+    ctx.defer().await?;
+
+    let from = load_backup_guild(&ctx, guild_id, &a, password_a.as_deref()).await?;
+    let to = load_backup_guild(&ctx, guild_id, &b, password_b.as_deref()).await?;
+
+    let diff = BackupDiff::new(&from, &to);
+
+    if diff.is_empty() {
+        ctx.say(format!("No differences found between `{}` and `{}`", a, b))
+            .await?;
+        return Ok(());
+    }
+
+    let mut embed = CreateEmbed::default()
+        .title("Backup Diff")
+        .description(format!("Changes going from `{}` to `{}`", a, b));
+
+    for (name, count) in diff.summary() {
+        embed = embed.field(name, count.to_string(), true);
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed).attachment(
+        serenity::all::CreateAttachment::bytes(diff.to_text().into_bytes(), "diff.txt"),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Parses a schedule interval (daily, weekly or a duration such as `12h`) into seconds
+fn parse_schedule_interval(interval: &str) -> Result<i64, Error> {
+    let secs = match interval.trim().to_lowercase().as_str() {
+        "daily" => 86400,
+        "weekly" => 604800,
+        interval => {
+            let (dur, unit) = parse_duration_string(interval)?;
+            dur.checked_mul(unit.to_seconds())
+                .ok_or("Backup interval is too large")?
+        }
+    };
+
+    if secs < 3600 {
+        return Err("Backups cannot be scheduled more often than once an hour".into());
+    }
+
+    // Ensure at most a year
+    if secs > 31536000 {
+        return Err("Backups must be scheduled at least once a year (31536000 seconds)".into());
+    }
+
+    Ok(secs as i64)
+}
+
+/// Manage automatic backups of the server
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "schedule",
+    subcommands(
+        "backups_schedule_set",
+        "backups_schedule_view",
+        "backups_schedule_delete"
+    )
+)]
+pub async fn backups_schedule(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Automatically create backups of the server on a schedule
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "set")]
+#[allow(clippy::too_many_arguments)] // This function needs these arguments due to poise
+pub async fn backups_schedule_set(
+    ctx: Context<'_>,
+
+    #[description = "How often to create backups: daily, weekly or a duration such as '12h'"]
+    interval: String,
+
+    #[description = "Number of most recent scheduled backups to keep. Defaults to 7"]
+    keep_last: Option<i32>,
+
+    #[description = "Number of weeks to keep one scheduled backup per week for. Defaults to 4"]
+    keep_weekly: Option<i32>,
+
+    #[description = "Whether to include messages in the backup (up to 500)"] messages: Option<bool>,
+
+    #[description = "Channels to backup messages from, otherwise all channels will have messages backed up"]
+    channels: Option<String>,
+
+    #[description = "Whether to include attachments in the backup. Requires 'messages' to be enabled"]
+    attachments: Option<bool>,
+
+    #[description = "What assets to back up in comma-seperated form (icon,splash,banner)"]
+    backup_guild_assets: Option<String>,
+
+    #[description = "Roll over leftover message quotas to other channels. May make backups slower. Defaults to true"]
+    rollover_leftovers: Option<bool>,
+
+    #[description = "Whether to ignore errors while backing up messages or not and skip these channels"]
+    ignore_message_backup_errors: Option<bool>,
+
+    #[description = "The maximum number of messages to backup. Defaults to 500"]
+    max_messages: Option<i32>,
+
+    #[description = "The number of messages per channel to backup. Defaults to 100"]
+    per_channel: Option<i32>,
+
+    #[description = "Specific channel allocation overrides. Format: channel_id=number,channel_id=number"]
+    special_allocations: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.schedule".into(),
+    )
+    .await?;
+
+    let interval_secs = parse_schedule_interval(&interval)?;
+    let keep_last = keep_last.unwrap_or(7);
+    let keep_weekly = keep_weekly.unwrap_or(4);
+
+    if keep_last < 1 || keep_weekly < 0 {
+        return Err("At least one backup must be kept and keep_weekly cannot be negative".into());
+    }
+
+    // Scheduled backups are never encrypted as the password would need to be stored
+    let options = create_backup_opts_serde(
+        messages,
+        &channels,
+        attachments,
+        backup_guild_assets,
+        rollover_leftovers,
+        ignore_message_backup_errors,
+        max_messages,
+        per_channel,
+        special_allocations,
+        None,
+    )?;
+
+    sqlx::query(
+        "INSERT INTO backups__schedules (guild_id, interval_secs, options, keep_last, keep_weekly, next_run_at, created_by) VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(secs => $2), $6) ON CONFLICT (guild_id) DO UPDATE SET interval_secs = EXCLUDED.interval_secs, options = EXCLUDED.options, keep_last = EXCLUDED.keep_last, keep_weekly = EXCLUDED.keep_weekly, next_run_at = EXCLUDED.next_run_at, created_by = EXCLUDED.created_by",
+    )
+    .bind(guild_id.to_string())
+    .bind(interval_secs)
+    .bind(options)
+    .bind(keep_last)
+    .bind(keep_weekly)
+    .bind(ctx.author().id.to_string())
+    .execute(&ctx.data().pool)
+    .await?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("Backup Schedule Saved")
+                .description(format!(
+                    "A backup will be created every {} seconds, starting <t:{}:R>.\n\nKeeping the last {} scheduled backups and one backup per week for {} weeks. Manually created backups are never pruned.",
+                    interval_secs,
+                    chrono::Utc::now().timestamp() + interval_secs,
+                    keep_last,
+                    keep_weekly
+                )),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// View the automatic backup schedule of the server
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "view")]
+pub async fn backups_schedule_view(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.list".into(),
+    )
+    .await?;
+
+    #[derive(sqlx::FromRow)]
+    struct ScheduleRecord {
+        interval_secs: i64,
+        keep_last: i32,
+        keep_weekly: i32,
+        next_run_at: chrono::DateTime<chrono::Utc>,
+        last_run_at: Option<chrono::DateTime<chrono::Utc>>,
+        created_by: String,
+    }
+
+    let rec: Option<ScheduleRecord> = sqlx::query_as(
+        "SELECT interval_secs, keep_last, keep_weekly, next_run_at, last_run_at, created_by FROM backups__schedules WHERE guild_id = $1",
+    )
+    .bind(guild_id.to_string())
+    .fetch_optional(&ctx.data().pool)
+    .await?;
+
+    let Some(rec) = rec else {
+        return Err(
+            "This server has no backup schedule. Use `/backups schedule set` to create one".into(),
+        );
+    };
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("Backup Schedule")
+                .field("Interval", format!("{} seconds", rec.interval_secs), true)
+                .field("Keep Last", rec.keep_last.to_string(), true)
+                .field("Keep Weekly", format!("{} weeks", rec.keep_weekly), true)
+                .field(
+                    "Next Backup",
+                    format!("<t:{}:R>", rec.next_run_at.timestamp()),
+                    true,
+                )
+                .field(
+                    "Last Backup",
+                    match rec.last_run_at {
+                        Some(last_run_at) => format!("<t:{}:R>", last_run_at.timestamp()),
+                        None => "Never".to_string(),
+                    },
+                    true,
+                )
+                .field("Created By", format!("<@{}>", rec.created_by), true),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Stop automatically creating backups of the server
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "delete")]
+pub async fn backups_schedule_delete(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.schedule".into(),
+    )
+    .await?;
+
+    let res = sqlx::query("DELETE FROM backups__schedules WHERE guild_id = $1")
+        .bind(guild_id.to_string())
+        .execute(&ctx.data().pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err("This server has no backup schedule".into());
+    }
+
+    ctx.say("Backup schedule deleted. Existing backups have not been deleted")
+        .await?;
+
+    Ok(())
+}
+
+/// Downloads the backup being restored from either the attachment or the backup job
+async fn download_backup_source(
+    data: &silverpelt::data::Data,
+    backup_file: &Option<serenity::all::Attachment>,
+    backup_job: &Option<jobserver::Job>,
+) -> Result<Vec<u8>, Error> {
+    match (backup_file, backup_job) {
+        (Some(backup_file), _) if backup_file.size as u64 > backupfile::MAX_BACKUP_SIZE => {
+            Err(backupfile::too_large().into())
+        }
+        (Some(backup_file), _) => Ok(backup_file
+            .download()
+            .await
+            .map_err(|e| format!("Failed to download backup: {}", e))?),
+        (None, Some(job)) => backupfile::download(data, job).await,
+        (None, None) => Err("Failed to get backup".into()),
+    }
+}
+
 #[derive(poise::ChoiceParameter)]
 enum ChannelRestoreMode {
     #[name = "full"]
@@ -990,7 +1852,8 @@ pub async fn backups_restore(
         serenity::all::Attachment,
     >,
 
-    #[description = "The job id of the backup to restore"] backup_id: Option<String>,
+    #[description = "The job id of the backup to restore. May belong to another server you manage"]
+    backup_id: Option<String>,
 
     #[description = "Password to decrypt backup with. Should not be reused"] password: Option<
         String,
@@ -1011,6 +1874,19 @@ pub async fn backups_restore(
 
     #[description = "Whether to ignore errors while restoring or not"]
     ignore_restore_errors: Option<bool>,
+
+    #[description = "Preview the changes the restore will make before executing it"] plan: Option<
+        bool,
+    >,
+
+    #[description = "Give restored channels and roles new IDs. Defaults to true for another server's backup"]
+    remap_ids: Option<bool>,
+
+    #[description = "Re-import AntiRaid's settings, templates and permissions if the backup includes them"]
+    import_antiraid_config: Option<bool>,
+
+    #[description = "What to do with AntiRaid settings that already exist. Defaults to skip"]
+    config_conflict_mode: Option<ConflictMode>,
 ) -> Result<(), Error> {
     let data = ctx.data();
 
@@ -1037,8 +1913,10 @@ pub async fn backups_restore(
         return Err("You must provide either a backup file or a backup id".into());
     }
 
+    let mut backup_job = None;
+    let mut cross_guild = false;
     let backup_url = {
-        if let Some(backup_file) = backup_file {
+        if let Some(ref backup_file) = backup_file {
             backup_file.url.to_string()
         } else {
             let Some(backup_id) = backup_id else {
@@ -1050,18 +1928,59 @@ pub async fn backups_restore(
                 .await
                 .map_err(|e| format!("Failed to get backup: {}", e))?;
 
-            if job.guild_id != guild_id {
-                return Err("Backup job is not owned by this server".into());
-            }
-
             let Some(path) = job.get_file_path() else {
                 return Err("Failed to find backup storage path".into());
             };
 
+            if job.guild_id != guild_id {
+                // Cloning another server requires being able to view its backups
+                crate::botlib::permission_checks::check_permissions(
+                    job.guild_id,
+                    ctx.author().id,
+                    &ctx.data().pool,
+                    ctx.serenity_context(),
+                    &ctx.data().reqwest,
+                    &Some(ctx),
+                    "backups.list".into(),
+                )
+                .await
+                .map_err(|e| {
+                    format!(
+                        "You need the `backups.list` permission in the server owning this backup: {}",
+                        e
+                    )
+                })?;
+
+                if job.name != "guild_create_backup" {
+                    return Err("Job is not a backup".into());
+                }
+
+                cross_guild = true;
+            }
+
+            backup_job = Some(job);
+
             format!("job:///{}", path)
         }
     };
 
+    if remap_ids == Some(true) && !CONFIG.backups.remap_ids {
+        return Err("Giving restored channels and roles new IDs is not supported yet".into());
+    }
+
+    // Another server's backup is remapped by default, so restoring one without remapping must be asked for explicitly
+    let remap_ids = match remap_ids {
+        Some(remap_ids) => remap_ids,
+        None if cross_guild && !CONFIG.backups.remap_ids => {
+            return Err("Channels and roles of another server's backup cannot be given new IDs yet. Set `remap_ids` to false to restore it with its original IDs".into());
+        }
+        None => cross_guild,
+    };
+
+    let channel_restore_mode = channel_restore_mode.unwrap_or(ChannelRestoreMode::Full);
+
+    let role_restore_mode = role_restore_mode.unwrap_or(RoleRestoreMode::Full);
+
     let protected_channels = {
         let mut p = Vec::new();
         let protected_channels = protected_channels.unwrap_or_default();
@@ -1102,6 +2021,151 @@ pub async fn backups_restore(
         p
     };
 
+    // AntiRaid's configuration is only imported once the restore has completed so that role and channel IDs
+    // can be mapped onto the restored ones
+    let config_import = if import_antiraid_config.unwrap_or(false) {
+        let Some(ref job) = backup_job else {
+            return Err("AntiRaid's configuration can only be imported from a backup ID".into());
+        };
+
+        #[derive(sqlx::FromRow)]
+        struct ConfigRecord {
+            has_antiraid_config: bool,
+        }
+
+        let rec: Option<ConfigRecord> = sqlx::query_as(
+            "SELECT has_antiraid_config FROM backups__job_metadata WHERE job_id = $1",
+        )
+        .bind(job.id)
+        .fetch_optional(&data.pool)
+        .await?;
+
+        if !rec.is_some_and(|r| r.has_antiraid_config) {
+            return Err("This backup does not include AntiRaid's configuration".into());
+        }
+
+        ctx.defer().await?;
+
+        // Read from the bucket of the server owning the backup, which may be another server
+        let config = antiraidconfig::load(data, job.guild_id, job.id, password.as_deref()).await?;
+
+        let contents = download_backup_source(data, &backup_file, &backup_job).await?;
+        let backup = BackupGuild::from_backup(contents, password.as_deref())?;
+
+        Some((config, backup))
+    } else {
+        None
+    };
+
+    if plan.unwrap_or(false) {
+        ctx.defer().await?;
+
+        let contents = download_backup_source(data, &backup_file, &backup_job).await?;
+
+        let backup = BackupGuild::from_backup(contents, password.as_deref())?;
+        let live = BackupGuild::live(&ctx.serenity_context().http, guild_id).await?;
+
+        let restore_plan = RestorePlan::new(
+            &backup,
+            &live,
+            guild_id,
+            &RestorePlanOptions {
+                channel_restore_mode: &channel_restore_mode.to_string(),
+                role_restore_mode: &role_restore_mode.to_string(),
+                protected_channels: &protected_channels,
+                protected_roles: &protected_roles,
+                remap_ids,
+            },
+        );
+
+        let mut embed = CreateEmbed::default()
+            .title("Restore Plan")
+            .description(format!(
+                "Channel restore mode: `{}`\nRole restore mode: `{}`\n\nThe full list of changes is attached. Press **Execute** to start the restore",
+                channel_restore_mode, role_restore_mode
+            ));
+
+        for (name, count) in restore_plan.summary() {
+            embed = embed.field(name, count.to_string(), true);
+        }
+
+        let plan_msg = ctx
+            .send(
+                poise::CreateReply::default()
+                    .embed(embed)
+                    .attachment(serenity::all::CreateAttachment::bytes(
+                        restore_plan.to_text().into_bytes(),
+                        "restore_plan.txt",
+                    ))
+                    .components(vec![serenity::all::CreateActionRow::Buttons(
+                        vec![
+                            serenity::all::CreateButton::new("backups_restore_plan_execute")
+                                .label("Execute")
+                                .style(serenity::all::ButtonStyle::Danger),
+                            serenity::all::CreateButton::new("backups_restore_plan_cancel")
+                                .label("Cancel")
+                                .style(serenity::all::ButtonStyle::Secondary),
+                        ]
+                        .into(),
+                    )]),
+            )
+            .await?
+            .into_message()
+            .await?;
+
+        let collector = plan_msg
+            .id
+            .await_component_interaction(ctx.serenity_context().shard.clone())
+            .author_id(ctx.author().id)
+            .timeout(Duration::from_secs(120))
+            .await;
+
+        let Some(item) = collector else {
+            ctx.say("You took too long to respond, the restore was not started")
+                .await?;
+            return Ok(());
+        };
+
+        item.defer(&ctx.serenity_context().http).await?;
+
+        if item.data.custom_id.as_str() != "backups_restore_plan_execute" {
+            ctx.say("Restore cancelled").await?;
+            return Ok(());
+        }
+    }
+
+    // The restore job can only read from this server's bucket, so another server's backup is copied over
+    // only once the restore is going ahead. The copy counts towards this server's quota while it exists
+    let copied_path = match backup_job {
+        Some(ref job) if cross_guild => {
+            let Some(path) = job.get_file_path() else {
+                return Err("Failed to find backup storage path".into());
+            };
+
+            ctx.defer().await?;
+
+            let contents = backupfile::download(data, job).await?;
+
+            let usage = backupusage::guild_usage(data, guild_id).await?;
+            if usage.used + contents.len() as u64 > usage.quota {
+                return Err(format!(
+                    "Copying this backup ({}) would exceed this server's backup storage quota ({})",
+                    backupusage::format_bytes(contents.len() as u64),
+                    usage.summary()
+                )
+                .into());
+            }
+
+            data.object_store
+                .upload_file(&guild_bucket(guild_id), &path, contents)
+                .await
+                .map_err(|e| format!("Failed to copy backup to this server: {}", e))?;
+
+            Some(path)
+        }
+        _ => None,
+    };
+
     let base_message = ctx
         .send(
             poise::CreateReply::default().embed(
@@ -1112,20 +2176,264 @@ pub async fn backups_restore(
         )
         .await?;
 
-    let json = serde_json::json!({
+    let mut json = serde_json::json!({
         "Options": {
             "IgnoreRestoreErrors": ignore_restore_errors.unwrap_or(false),
             "ProtectedChannels": protected_channels,
             "ProtectedRoles": protected_roles,
             "BackupSource": backup_url,
             "Decrypt": password.unwrap_or_default(),
-            "ChannelRestoreMode": channel_restore_mode.unwrap_or(ChannelRestoreMode::Full).to_string(),
-            "RoleRestoreMode": role_restore_mode.unwrap_or(RoleRestoreMode::Full).to_string(),
+            "ChannelRestoreMode": channel_restore_mode.to_string(),
+            "RoleRestoreMode": role_restore_mode.to_string(),
         },
     });
 
+    if CONFIG.backups.remap_ids {
+        json["Options"]["RemapIds"] = serde_json::json!(remap_ids);
+    }
+
+    let completed = run_restore_job(ctx, guild_id, &base_message, json, copied_path).await?;
+
+    let Some((config, backup)) = config_import else {
+        return Ok(());
+    };
+
+    if !completed {
+        ctx.say("AntiRaid's configuration was not imported as the restore did not complete")
+            .await?;
+        return Ok(());
+    }
+
+    import_antiraid_config(
+        ctx,
+        guild_id,
+        &config,
+        &backup,
+        config_conflict_mode.unwrap_or(ConflictMode::Skip),
+    )
+    .await
+}
+
+/// Imports AntiRaid's configuration from a backup once the backup itself has been restored
+///
+/// Role permissions and templates grant permissions and capabilities, so they are only imported once confirmed.
+/// Lockdowns are re-applied rather than imported so that they actually lock the restored channels and roles
+async fn import_antiraid_config(
+    ctx: Context<'_>,
+    guild_id: serenity::all::GuildId,
+    config: &antiraidconfig::ExportedConfig,
+    backup: &BackupGuild,
+    mode: ConflictMode,
+) -> Result<(), Error> {
+    let data = ctx.data();
+
+    let live = BackupGuild::live(&ctx.serenity_context().http, guild_id).await?;
+    let id_map = antiraidconfig::id_map(backup, &live);
+
+    let templates = antiraidconfig::templates(config);
+    let role_permissions = antiraidconfig::role_permissions(config);
+
+    let reviewed = if templates.is_empty() && role_permissions == 0 {
+        false
+    } else {
+        if !templates.is_empty() {
+            crate::botlib::permission_checks::check_permissions(
+                guild_id,
+                ctx.author().id,
+                &data.pool,
+                ctx.serenity_context(),
+                &data.reqwest,
+                &Some(ctx),
+                "bot.load".into(),
+            )
+            .await
+            .map_err(|e| {
+                format!(
+                    "Importing templates requires the `bot.load` permission: {}",
+                    e
+                )
+            })?;
+        }
+
+        let mut desc = "This backup includes role permissions and/or templates. Only import them if you trust the backup, everything else is imported either way".to_string();
+
+        if role_permissions > 0 {
+            desc += &format!("\n\n**Role permissions:** {} roles", role_permissions);
+        }
+
+        for (name, caps) in &templates {
+            desc += &format!(
+                "\n**Template `{}`:** {}",
+                name.replace('`', "\\`"),
+                if caps.is_empty() {
+                    "no capabilities".to_string()
+                } else {
+                    caps.iter()
+                        .map(|c| format!("`{}`", c.replace('`', "\\`")))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            );
+        }
+
+        let msg = ctx
+            .send(
+                poise::CreateReply::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("Import Role Permissions And Templates?")
+                            .description(truncate(&desc, 4000)),
+                    )
+                    .components(vec![serenity::all::CreateActionRow::Buttons(
+                        vec![
+                            serenity::all::CreateButton::new("backups_restore_config_import")
+                                .label("Import")
+                                .style(serenity::all::ButtonStyle::Danger),
+                            serenity::all::CreateButton::new("backups_restore_config_skip")
+                                .label("Skip")
+                                .style(serenity::all::ButtonStyle::Secondary),
+                        ]
+                        .into(),
+                    )]),
+            )
+            .await?
+            .into_message()
+            .await?;
+
+        let item = msg
+            .id
+            .await_component_interaction(ctx.serenity_context().shard.clone())
+            .author_id(ctx.author().id)
+            .timeout(Duration::from_secs(120))
+            .await;
+
+        match item {
+            Some(item) => {
+                item.defer(&ctx.serenity_context().http).await?;
+                item.data.custom_id.as_str() == "backups_restore_config_import"
+            }
+            None => false,
+        }
+    };
+
+    let result =
+        antiraidconfig::import(&data.pool, guild_id, config, mode, &id_map, reviewed).await?;
+
+    let mut status = vec![format!(
+        ":white_check_mark: Imported {} rows, skipped {} rows",
+        result.imported, result.skipped
+    )];
+
+    if !reviewed && (!templates.is_empty() || role_permissions > 0) {
+        status.push(":yellow_circle: Role permissions and templates were not imported".to_string());
+    }
+
+    if !result.templates.is_empty() {
+        if let Err(e) = AntiraidEvent::OnStartup(result.templates.clone())
+            .dispatch_to_template_worker_and_nowait(data, guild_id, &template_dispatch_data())
+            .await
+        {
+            status.push(format!(":x: Failed to start the imported templates: {}", e));
+        }
+    }
+
+    for failure in result.failed.iter().take(10) {
+        status.push(format!(":x: {}", failure));
+    }
+
+    let pending = antiraidconfig::pending_lockdowns(config, &id_map);
+
+    if !pending.is_empty() {
+        let mut lockdowns = lockdowns::LockdownSet::guild(
+            guild_id,
+            LockdownData::new(
+                ctx.serenity_context().cache.clone(),
+                ctx.serenity_context().http.clone(),
+                data.pool.clone(),
+                data.reqwest.clone(),
+                sandwich_config(),
+            ),
+        )
+        .await
+        .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+        for lockdown in pending {
+            let string_form = lockdown.string_form();
+
+            // Restoring onto the same server may leave its lockdowns in place
+            if lockdowns
+                .lockdowns()
+                .iter()
+                .any(|l| l.r#type.string_form() == string_form)
+            {
+                continue;
+            }
+
+            let (lockdown_type, perm) =
+                match create_lockdown_mode(&lockdown.lockdown_type, lockdown.target.as_deref()) {
+                    Ok(mode) => mode,
+                    Err(e) => {
+                        status.push(format!(":x: Lockdown `{}`: {}", string_form, e));
+                        continue;
+                    }
+                };
+
+            if let Err(e) = crate::botlib::permission_checks::check_permissions(
+                guild_id,
+                ctx.author().id,
+                &data.pool,
+                ctx.serenity_context(),
+                &data.reqwest,
+                &Some(ctx),
+                perm.into(),
+            )
+            .await
+            {
+                status.push(format!(":x: Lockdown `{}`: {}", string_form, e));
+                continue;
+            }
+
+            if lockdowns.lockdowns().is_empty() {
+                record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+            }
+
+            match lockdowns.apply(lockdown_type, &lockdown.reason).await {
+                Ok(_) => {
+                    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+                    status.push(format!(
+                        ":white_check_mark: Re-applied lockdown `{}`",
+                        string_form
+                    ));
+                }
+                Err(e) => status.push(format!(":x: Lockdown `{}`: {}", string_form, e)),
+            }
+        }
+    }
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("AntiRaid Configuration Restored")
+                .description(truncate(&status.join("\n"), 4000)),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Spawns a restore job and reports its progress until it finishes, returning whether it completed
+async fn run_restore_job(
+    ctx: Context<'_>,
+    guild_id: serenity::all::GuildId,
+    base_message: &poise::ReplyHandle<'_>,
+    json: serde_json::Value,
+    copied_path: Option<String>,
+) -> Result<bool, Error> {
+    let data = ctx.data();
+
     // Restore backup
-    let restore_id = jobserver::spawn::spawn_task(
+    let restore_id = match jobserver::spawn::spawn_task(
         &data.reqwest,
         &jobserver::Spawn {
             name: "guild_restore_backup".to_string(),
@@ -1133,13 +2441,43 @@ pub async fn backups_restore(
             create: true,
             execute: true,
             id: None,
-            guild_id: ctx.guild_id().unwrap().to_string(),
+            guild_id: guild_id.to_string(),
         },
         &CONFIG.base_ports.jobserver_base_addr,
         CONFIG.base_ports.jobserver,
     )
-    .await?
-    .id;
+    .await
+    {
+        Ok(spawned) => spawned.id,
+        Err(e) => {
+            if let Some(path) = copied_path {
+                if let Err(e) = data
+                    .object_store
+                    .delete(&guild_bucket(guild_id), &path)
+                    .await
+                {
+                    log::error!("Failed to remove copied backup {}: {}", path, e);
+                }
+            }
+
+            return Err(e);
+        }
+    };
+
+    // The job reads the copy while it runs, so it is only removed by the restore_copies task once the job is done
+    if let Some(path) = copied_path {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO backups__restore_copies (restore_job_id, guild_id, path) VALUES ($1, $2, $3)",
+        )
+        .bind(restore_id.parse::<Uuid>()?)
+        .bind(guild_id.to_string())
+        .bind(&path)
+        .execute(&data.pool)
+        .await
+        {
+            log::error!("Failed to record copied backup {}: {}", path, e);
+        }
+    }
 
     base_message
         .edit(
@@ -1161,9 +2499,13 @@ pub async fn backups_restore(
         jobserver::poll::PollTaskOptions::default(),
     )?);
 
+    let mut completed = false;
+
     while let Some(job) = stream.next().await {
         match job {
             Ok(Some(job)) => {
+                completed = job.state == "completed";
+
                 let new_job_msg = embed_job(&CONFIG.sites.api, &job, vec![], true)?;
 
                 base_message
@@ -1187,5 +2529,126 @@ pub async fn backups_restore(
         }
     }
 
+    Ok(completed)
+}
+
+/// Restores the backed up messages of a (deleted) channel into another channel using a webhook
+#[poise::command(
+    slash_command,
+    guild_only,
+    user_cooldown = "20",
+    guild_cooldown = "30",
+    rename = "restore_messages"
+)]
+pub async fn backups_restore_messages(
+    ctx: Context<'_>,
+    #[description = "The job id of the backup to restore messages from"] backup_id: String,
+    #[description = "The ID of the channel in the backup whose messages should be restored"]
+    source_channel: String,
+    #[description = "The channel to post the messages in"]
+    #[channel_types("Text")]
+    target_channel: serenity::all::GuildChannel,
+    #[description = "Password to decrypt backup with"] password: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "backups.restore".into(),
+    )
+    .await?;
+
+    let source_channel = parse_numeric_list::<ChannelId>(&source_channel, &REPLACE_CHANNEL)?
+        .into_iter()
+        .next()
+        .ok_or("Invalid source channel")?;
+
+    ctx.defer().await?;
+
+    let job = jobserver::Job::from_id(backup_id.trim().parse::<Uuid>()?, &ctx.data().pool)
+        .await
+        .map_err(|e| format!("Failed to get backup: {}", e))?;
+
+    if job.guild_id != guild_id {
+        return Err("Backup job is not owned by this server".into());
+    }
+
+    if job.name != "guild_create_backup" {
+        return Err("Job is not a backup".into());
+    }
+
+    let contents = backupfile::download(ctx.data(), &job).await?;
+    let sections = backupfile::read_sections(contents, password.as_deref())?;
+    let messages = backupfile::messages(&sections, source_channel)?;
+
+    if messages.is_empty() {
+        return Err("The backup has no messages for this channel".into());
+    }
+
+    let http = &ctx.serenity_context().http;
+
+    let webhook = target_channel
+        .id
+        .create_webhook(
+            http,
+            serenity::all::CreateWebhook::new("AntiRaid Restore")
+                .audit_log_reason("Restoring backed up messages"),
+        )
+        .await
+        .map_err(|e| format!("Failed to create webhook: {}", e))?;
+
+    let mut restored = 0;
+    let mut failed = 0;
+    for message in &messages {
+        let mut content = message.content.clone();
+
+        if !message.attachments.is_empty() {
+            content.push_str(&format!(
+                "\n*[{} attachment(s) not restored]*",
+                message.attachments.len()
+            ));
+        }
+
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        content = truncate(&content, 2000);
+
+        let mut execute = serenity::all::ExecuteWebhook::new()
+            .content(content)
+            .username(message.author.webhook_username())
+            .allowed_mentions(serenity::all::CreateAllowedMentions::new());
+
+        if let Some(avatar_url) = message.author.avatar_url() {
+            execute = execute.avatar_url(avatar_url);
+        }
+
+        match webhook.execute(http, true, execute).await {
+            Ok(_) => restored += 1,
+            Err(e) => {
+                log::warn!("Failed to restore message: {}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    if let Err(e) = webhook.delete(http).await {
+        log::warn!("Failed to delete restore webhook: {}", e);
+    }
+
+    ctx.say(format!(
+        "Restored {} messages into <#{}> ({} failed)",
+        restored, target_channel.id, failed
+    ))
+    .await?;
+
     Ok(())
 }
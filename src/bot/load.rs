@@ -1,8 +1,13 @@
-use antiraid_types::ar_event::AntiraidEvent;
-use serenity::all::{CreateActionRow, CreateButton, CreateEmbed};
-use silverpelt::ar_event::AntiraidEventOperations;
+use serenity::all::CreateEmbed;
 
-use crate::bot::template_dispatch_data;
+use crate::bot::templates::{
+    collect_template_config, confirm_capabilities, dispatch_template_changes,
+    installed_shop_template, shop_versions,
+};
+use crate::botlib::templatecaps::CapabilityDiff;
+use crate::botlib::templateconfig;
+use crate::botlib::templateversions::resolve_version;
+use crate::botlib::text::truncate;
 
 pub async fn load_autocomplete<'a>(
     ctx: crate::Context<'_>,
@@ -48,7 +53,8 @@ pub async fn load(
     ctx: crate::Context<'_>,
     #[autocomplete = "load_autocomplete"] template_name: String,
     #[description = "Channel to send errors to"] error_channel: Option<serenity::all::GuildChannel>,
-    #[description = "Version of the template to load. Defaults to latest"] version: Option<String>,
+    #[description = "Version or requirement (e.g. ^1.2) of the template to load. Defaults to latest"]
+    version: Option<String>,
 ) -> Result<(), crate::Error> {
     let guild_id = ctx
         .guild_id()
@@ -65,9 +71,27 @@ pub async fn load(
     )
     .await?;
 
-    let data = ctx.data();
+    load_template(
+        &ctx,
+        guild_id,
+        &template_name,
+        error_channel.map(|c| c.id),
+        version.as_deref().unwrap_or("latest"),
+    )
+    .await
+}
 
-    let version = version.as_deref().unwrap_or("latest");
+/// Asks for confirmation and loads a shop template into a guild
+///
+/// Callers must check the `bot.load` permission first
+pub(super) async fn load_template(
+    ctx: &crate::Context<'_>,
+    guild_id: serenity::all::GuildId,
+    template_name: &str,
+    error_channel: Option<serenity::all::ChannelId>,
+    version: &str,
+) -> Result<(), crate::Error> {
+    let data = ctx.data();
 
     #[derive(sqlx::FromRow)]
     struct LoadData {
@@ -76,168 +100,157 @@ pub async fn load(
         events: Vec<String>,
         language: String,
         allowed_caps: Vec<String>,
+        config_schema: serde_json::Value,
+        content: serde_json::Value,
     }
 
-    let rec = {
-        if version == "latest" {
-            let rec: Option<LoadData> = sqlx::query_as(
-                "SELECT version, description, events, language, allowed_caps FROM template_shop WHERE name = $1 ORDER BY version DESC LIMIT 1",
-            )
-            .bind(&template_name)
-            .fetch_optional(&data.pool)
-            .await?;
+    let versions = shop_versions(&data.pool).await?;
 
-            if let Some(rec) = rec {
-                rec
-            } else {
-                return Err("No template with that name found in the shop".into());
-            }
-        } else {
-            let rec: Option<LoadData> = sqlx::query_as(
-                "SELECT version, description, events, language, allowed_caps FROM template_shop WHERE name = $1 AND version = $2",
-            )
-            .bind(&template_name)
-            .bind(version)
-            .fetch_optional(&data.pool)
-            .await?;
+    let available = versions
+        .iter()
+        .filter(|v| v.name == template_name)
+        .map(|v| v.version.clone())
+        .collect::<Vec<_>>();
 
-            if let Some(rec) = rec {
-                rec
-            } else {
-                return Err("No template with that name and version found in the shop".into());
-            }
-        }
-    };
+    if available.is_empty() {
+        return Err("No template with that name found in the shop".into());
+    }
+
+    let resolved_version = resolve_version(&available, version)?;
+
+    let rec: LoadData = sqlx::query_as(
+        "SELECT version, description, events, language, allowed_caps, config_schema, content FROM template_shop WHERE name = $1 AND version = $2",
+    )
+    .bind(template_name)
+    .bind(&resolved_version)
+    .fetch_one(&data.pool)
+    .await?;
+
+    let schema = templateconfig::parse_schema(rec.config_schema)?;
+
+    let installed = installed_shop_template(&data.pool, guild_id, &versions, template_name).await?;
+
+    let diff = CapabilityDiff::new(
+        installed
+            .as_ref()
+            .map(|t| (t.allowed_caps.as_slice(), t.events.as_slice())),
+        &rec.allowed_caps,
+        &rec.events,
+    );
+
+    let mut description = format!(
+        "Are you sure you want to load the template `{} v{}`?",
+        template_name.replace('`', "\\`"),
+        rec.version.replace('`', "\\`"),
+    );
+
+    if version != rec.version {
+        description += &format!(
+            "\n\nResolved `{}` to version `{}`",
+            version.replace('`', "\\`"),
+            rec.version.replace('`', "\\`")
+        );
+    }
+
+    if let Some(ref installed) = installed {
+        description += &format!(
+            "\n\nThis replaces the installed `{}`, changes are shown against it",
+            installed.name.replace('`', "\\`")
+        );
+    }
 
     // Ask the user to confirm that they want to load the template
-    let confirm = ctx
-        .send(
-            poise::CreateReply::new()
-                .embed(
-                    CreateEmbed::default()
-                        .title("Load Template?")
-                        .description(format!(
-                            "Are you sure you want to load the template `{} v{}`?",
-                            template_name.replace('`', "\\`"),
-                            rec.version.replace('`', "\\`")
-                        ))
-                        .field(
-                            "Description",
-                            if rec.description.len() > 300 {
-                                format!("{}...", &rec.description[..300])
-                            } else {
-                                rec.description
-                            },
-                            false,
-                        )
-                        .field(
-                            "Language",
-                            if rec.language.len() > 300 {
-                                format!("{}...", &rec.language[..300])
-                            } else {
-                                rec.language
-                            },
-                            false,
-                        )
-                        .field(
-                            "Events",
-                            {
-                                let events_str = rec
-                                    .events
-                                    .iter()
-                                    .map(|e| e.to_lowercase())
-                                    .collect::<Vec<_>>()
-                                    .join(", ");
-                                if events_str.len() > 300 {
-                                    format!("``{}...``", &events_str[..300])
-                                } else {
-                                    format!("``{}``", events_str)
-                                }
-                            },
-                            false,
-                        )
-                        .field(
-                            "Capabilities",
-                            {
-                                let allowed_caps_str = rec.allowed_caps.join(", ");
-                                if allowed_caps_str.len() > 300 {
-                                    format!("``{}...``", &allowed_caps_str[..300])
-                                } else {
-                                    format!("``{}``", allowed_caps_str)
-                                }
-                            },
-                            false,
-                        ),
-                )
-                .components(vec![CreateActionRow::buttons(vec![
-                    CreateButton::new("yes")
-                        .label("Yes")
-                        .style(serenity::all::ButtonStyle::Danger),
-                    CreateButton::new("no")
-                        .label("No")
-                        .style(serenity::all::ButtonStyle::Primary),
-                ])]),
-        )
-        .await?
-        .into_message()
-        .await?;
-
-    let int_col = confirm
-        .id
-        .await_component_interactions(ctx.serenity_context().shard.clone())
-        .author_id(ctx.author().id);
-
-    let Some(confirm) = int_col.await else {
-        return Err("No response".into());
+    let Some((confirm, allowed_caps)) = confirm_capabilities(
+        ctx,
+        CreateEmbed::default()
+            .title("Load Template?")
+            .description(description)
+            .field("Description", truncate(&rec.description, 1024), false)
+            .field("Language", truncate(&rec.language, 1024), false),
+        &diff,
+    )
+    .await?
+    else {
+        return Ok(());
     };
 
-    if confirm.data.custom_id != "yes" {
-        confirm
-            .create_response(
-                ctx.http(),
-                serenity::all::CreateInteractionResponse::Message(
-                    serenity::all::CreateInteractionResponseMessage::new()
-                        .content("Cancelled successfully!"),
-                ),
-            )
+    // Collect the settings the template asks for, they reach the template as a module next to the shop's files
+    let (config, content) = if schema.is_empty() {
+        (serde_json::json!({}), serde_json::Value::Null)
+    } else {
+        confirm.defer(ctx.http()).await?;
+
+        let Some(config) = collect_template_config(ctx, &schema).await? else {
+            return Ok(());
+        };
+
+        let content = templateconfig::with_config(rec.content, &config)?;
+
+        (config, content)
+    };
+
+    // Add template to servers list of templates
+    // `latest` follows new versions, requirements are pinned to the version they resolved to
+    let name = silverpelt::templates::create_shop_template(
+        template_name,
+        if version == "latest" {
+            "latest"
+        } else {
+            &rec.version
+        },
+    );
+
+    let mut tx = data.pool.begin().await?;
+
+    // Loading another version of an installed template replaces it, so only the capabilities confirmed above are granted
+    if let Some(ref installed) = installed {
+        sqlx::query("DELETE FROM guild_templates WHERE guild_id = $1 AND name = $2")
+            .bind(guild_id.to_string())
+            .bind(&installed.name)
+            .execute(&mut *tx)
             .await?;
-        return Ok(());
     }
 
-    // Add template to servers list of templates
-    let name = silverpelt::templates::create_shop_template(&template_name, version);
     sqlx::query(
-        "INSERT INTO guild_templates (guild_id, name, content, events, allowed_caps, error_channel, created_by, last_updated_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        "INSERT INTO guild_templates (guild_id, name, content, events, allowed_caps, error_channel, created_by, last_updated_by, config) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
     )
     .bind(guild_id.to_string())
     .bind(&name)
-    .bind(serde_json::Value::Null)
+    .bind(&content)
     .bind(&rec.events)
-    .bind(&rec.allowed_caps)
-    .bind(match error_channel {
-        Some(channel) => channel.id.to_string(),
-        None => ctx.channel_id().to_string(),
-    })
+    .bind(&allowed_caps)
+    .bind(error_channel.unwrap_or(ctx.channel_id()).to_string())
     .bind(ctx.author().id.to_string())
     .bind(ctx.author().id.to_string())
-    .execute(&data.pool)
+    .bind(&config)
+    .execute(&mut *tx)
     .await
     .map_err(|e| format!("Failed to add template to guild: {:?}", e))?;
 
-    // Dispatch a OnStartup event for the template
-    AntiraidEvent::OnStartup(vec![name])
-        .dispatch_to_template_worker_and_nowait(&ctx.data(), guild_id, &template_dispatch_data())
-        .await
-        .map_err(|e| format!("Failed to dispatch OnStartup event: {:?}", e))?;
-
-    confirm
-        .create_response(
-            ctx.http(),
-            serenity::all::CreateInteractionResponse::Message(
-                serenity::all::CreateInteractionResponseMessage::new()
-                    .content("AntiRaid template loaded successfully!"),
-            ),
-        )
-        .await?;
+    tx.commit().await?;
+
+    // Dispatch a OnStartup event for the template, and for the version it replaced so the worker drops it
+    let removed = installed
+        .map(|installed| installed.name)
+        .filter(|installed| *installed != name)
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    dispatch_template_changes(data, guild_id, vec![name], &removed).await?;
+
+    if schema.is_empty() {
+        confirm
+            .create_response(
+                ctx.http(),
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new()
+                        .content("AntiRaid template loaded successfully!"),
+                ),
+            )
+            .await?;
+    } else {
+        ctx.say("AntiRaid template loaded successfully!").await?;
+    }
+
     Ok(())
 }
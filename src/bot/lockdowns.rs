@@ -1,7 +1,70 @@
+use serenity::all::{CreateActionRow, CreateButton, CreateEmbed, GuildId};
 use silverpelt::lockdowns::LockdownData;
 use sqlx::Row;
+use std::time::Duration;
 
-use crate::{bot::sandwich_config, Context, Error};
+use crate::botlib::text::truncate;
+use crate::{
+    bot::sandwich_config,
+    botlib::lockdown_snapshots::{self, ManagedScope, SnapshotKind},
+    Context, Error,
+};
+
+/// Records the current permission state of the guild for later verification
+///
+/// Errors are only logged as the lockdown itself has already been applied/removed at this point
+pub(crate) async fn record_snapshot(ctx: &Context<'_>, guild_id: GuildId, kind: SnapshotKind) {
+    lockdown_snapshots::record(ctx.http(), &ctx.data().pool, guild_id, kind).await;
+}
+
+/// Updates the stored snapshots after a lockdown has been removed
+pub(crate) async fn record_snapshot_after_remove(
+    ctx: &Context<'_>,
+    guild_id: GuildId,
+    now_empty: bool,
+) {
+    lockdown_snapshots::record_after_remove(ctx.http(), &ctx.data().pool, guild_id, now_empty)
+        .await;
+}
+
+/// Creates a lockdown mode given its type and target (if any), along with the kittycat permission needed to apply it
+pub(crate) fn create_lockdown_mode(
+    typ: &str,
+    target: Option<&str>,
+) -> Result<(Box<dyn lockdowns::LockdownMode>, &'static str), Error> {
+    match typ {
+        "tsl" => Ok((
+            Box::new(lockdowns::tsl::TraditionalServerLockdown {}),
+            "lockdowns.tsl",
+        )),
+        "qsl" => Ok((
+            Box::new(lockdowns::qsl::QuickServerLockdown {}),
+            "lockdowns.qsl",
+        )),
+        "scl" => {
+            let channel = target.ok_or("A target channel is required for a scl")?;
+            Ok((
+                Box::new(lockdowns::scl::SingleChannelLockdown(channel.parse()?)),
+                "lockdowns.scl",
+            ))
+        }
+        "role" => {
+            let role = target.ok_or("A target role is required for a role lockdown")?;
+            Ok((
+                Box::new(lockdowns::role::RoleLockdown(role.parse()?)),
+                "lockdowns.role",
+            ))
+        }
+        "vcl" => Ok((
+            Box::new(crate::botlib::vcl::VoiceChannelLockdown(match target {
+                Some(channel) => Some(channel.parse()?),
+                None => None,
+            })),
+            "lockdowns.vcl",
+        )),
+        _ => Err(format!("Unknown lockdown type: {}", typ).into()),
+    }
+}
 
 pub async fn lockdown_autocomplete<'a>(
     ctx: crate::Context<'_>,
@@ -58,7 +121,11 @@ pub async fn lockdown_autocomplete<'a>(
         "lockdowns_qsl",
         "lockdowns_scl",
         "lockdowns_role",
-        "lockdowns_remove"
+        "lockdowns_vcl",
+        "lockdowns_remove",
+        "lockdowns_remove_all",
+        "lockdowns_verify",
+        "lockdowns_autoverify"
     )
 )]
 pub async fn lockdowns(_ctx: Context<'_>) -> Result<(), Error> {
@@ -164,11 +231,17 @@ pub async fn lockdowns_tsl(ctx: Context<'_>, reason: String) -> Result<(), Error
 
     ctx.defer().await?;
 
+    if lockdowns.lockdowns().is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
     lockdowns
         .apply(Box::new(lockdown_type), &reason)
         .await
         .map_err(|e| format!("Error while applying lockdown: {}", e))?;
 
+    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+
     ctx.say("Lockdown started").await?;
 
     Ok(())
@@ -213,11 +286,17 @@ pub async fn lockdowns_qsl(ctx: Context<'_>, reason: String) -> Result<(), Error
 
     ctx.defer().await?;
 
+    if lockdowns.lockdowns().is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
     lockdowns
         .apply(Box::new(lockdown_type), &reason)
         .await
         .map_err(|e| format!("Error while applying lockdown: {}", e))?;
 
+    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+
     ctx.say("Lockdown started").await?;
 
     Ok(())
@@ -267,11 +346,17 @@ pub async fn lockdowns_scl(
 
     ctx.defer().await?;
 
+    if lockdowns.lockdowns().is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
     lockdowns
         .apply(Box::new(lockdown_type), &reason)
         .await
         .map_err(|e| format!("Error while applying lockdown: {}", e))?;
 
+    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+
     ctx.say("Lockdown started").await?;
 
     Ok(())
@@ -320,16 +405,154 @@ pub async fn lockdowns_role(
 
     ctx.defer().await?;
 
+    if lockdowns.lockdowns().is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
     lockdowns
         .apply(Box::new(lockdown_type), &reason)
         .await
         .map_err(|e| format!("Error while applying lockdown: {}", e))?;
 
+    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+
     ctx.say("Lockdown started").await?;
 
     Ok(())
 }
 
+#[poise::command(slash_command, guild_only, rename = "vcl")]
+/// Starts a voice channel lockdown, denying connecting and speaking in voice/stage channels
+pub async fn lockdowns_vcl(
+    ctx: Context<'_>,
+    #[description = "The voice/stage channel to lock. Defaults to all voice and stage channels"]
+    #[channel_types("Voice", "Stage")]
+    channel: Option<serenity::all::ChannelId>,
+    reason: String,
+    #[description = "Whether to disconnect members who are not allowlisted. Defaults to false"]
+    disconnect: Option<bool>,
+    #[description = "Members with this role will not be disconnected"] allowed_role: Option<
+        serenity::all::RoleId,
+    >,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "lockdowns.vcl".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    // Get the current lockdown set
+    let mut lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            ctx.serenity_context().cache.clone(),
+            ctx.serenity_context().http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+    // Create the lockdown
+    let lockdown_type = crate::botlib::vcl::VoiceChannelLockdown(channel);
+
+    ctx.defer().await?;
+
+    if lockdowns.lockdowns().is_empty() {
+        record_snapshot(&ctx, guild_id, SnapshotKind::Baseline).await;
+    }
+
+    lockdowns
+        .apply(Box::new(lockdown_type), &reason)
+        .await
+        .map_err(|e| format!("Error while applying lockdown: {}", e))?;
+
+    record_snapshot(&ctx, guild_id, SnapshotKind::Expected).await;
+
+    if !disconnect.unwrap_or(false) {
+        ctx.say("Lockdown started").await?;
+        return Ok(());
+    }
+
+    // Find members in the locked channels who should be disconnected
+    let to_disconnect = {
+        let Some(guild) = ctx.cache().guild(guild_id) else {
+            return Err(
+                "Lockdown started, but members could not be disconnected as the server is not cached"
+                    .into(),
+            );
+        };
+
+        guild
+            .voice_states
+            .iter()
+            .filter(|vs| match (vs.channel_id, channel) {
+                (Some(vs_channel), Some(channel)) => vs_channel == channel,
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+            .filter(|vs| {
+                let Some(ref member) = vs.member else {
+                    return true;
+                };
+
+                if member.user.bot() || member.user.id == guild.owner_id {
+                    return false;
+                }
+
+                // Allowlisted members either have the allowed role or can already move members
+                !member.roles.iter().any(|r| {
+                    Some(*r) == allowed_role
+                        || guild.roles.get(r).is_some_and(|role| {
+                            role.permissions.administrator() || role.permissions.move_members()
+                        })
+                })
+            })
+            .map(|vs| vs.user_id)
+            .collect::<Vec<_>>()
+    };
+
+    let mut failed = 0;
+
+    for user_id in to_disconnect.iter() {
+        if let Err(e) = guild_id
+            .edit_member(
+                ctx.http(),
+                *user_id,
+                serenity::all::EditMember::new()
+                    .disconnect_member()
+                    .audit_log_reason(&reason),
+            )
+            .await
+        {
+            log::error!("Failed to disconnect member {}: {}", user_id, e);
+            failed += 1;
+        }
+    }
+
+    ctx.say(format!(
+        "Lockdown started. Disconnected {}/{} members",
+        to_disconnect.len() - failed,
+        to_disconnect.len()
+    ))
+    .await?;
+
+    Ok(())
+}
+
 #[poise::command(slash_command, guild_only, rename = "remove")]
 /// Remove a lockdown by ID
 pub async fn lockdowns_remove(
@@ -374,7 +597,461 @@ pub async fn lockdowns_remove(
         .await
         .map_err(|e| format!("Error while applying lockdown: {}", e))?;
 
+    record_snapshot_after_remove(&ctx, guild_id, lockdowns.lockdowns().is_empty()).await;
+
     ctx.say("Lockdown removed").await?;
 
     Ok(())
 }
+
+/// Lockdown types that bulk operations can be restricted to
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+enum LockdownTypeFilter {
+    #[name = "tsl"]
+    Tsl,
+    #[name = "qsl"]
+    Qsl,
+    #[name = "scl"]
+    Scl,
+    #[name = "role"]
+    Role,
+    #[name = "vcl"]
+    Vcl,
+}
+
+impl LockdownTypeFilter {
+    /// Returns the string form prefix of the lockdown type
+    fn prefix(&self) -> &'static str {
+        match self {
+            LockdownTypeFilter::Tsl => "tsl",
+            LockdownTypeFilter::Qsl => "qsl",
+            LockdownTypeFilter::Scl => "scl",
+            LockdownTypeFilter::Role => "role",
+            LockdownTypeFilter::Vcl => "vcl",
+        }
+    }
+
+    /// Returns whether a lockdowns string form (e.g. `scl/1234`) is of this type
+    fn matches(&self, string_form: &str) -> bool {
+        string_form.split('/').next() == Some(self.prefix())
+    }
+}
+
+#[poise::command(slash_command, guild_only, rename = "remove_all")]
+/// Remove all lockdowns (optionally of a given type) in reverse order of application
+pub async fn lockdowns_remove_all(
+    ctx: Context<'_>,
+    #[description = "Only remove lockdowns of this type"] lockdown_type: Option<LockdownTypeFilter>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "lockdowns.remove_all".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    // Get the current lockdown set
+    let mut lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            ctx.serenity_context().cache.clone(),
+            ctx.serenity_context().http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+    // Newest lockdowns must be reverted first so that each revert restores
+    // the overwrites that were present when that lockdown was applied
+    let mut to_remove = lockdowns
+        .lockdowns()
+        .iter()
+        .filter(|l| match lockdown_type {
+            Some(filter) => filter.matches(&l.r#type.string_form()),
+            None => true,
+        })
+        .map(|l| (l.id, l.r#type.string_form(), l.created_at))
+        .collect::<Vec<_>>();
+
+    to_remove.sort_by(|a, b| b.2.cmp(&a.2));
+
+    if to_remove.is_empty() {
+        return Err("No matching active lockdowns".into());
+    }
+
+    ctx.defer().await?;
+
+    let total = to_remove.len();
+
+    let base_message = ctx
+        .send(
+            poise::CreateReply::new().embed(
+                serenity::all::CreateEmbed::new()
+                    .title("Removing Lockdowns...")
+                    .description(format!(":yellow_circle: Removing 0/{} lockdowns", total)),
+            ),
+        )
+        .await?;
+
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+
+    for (i, (id, typ, _)) in to_remove.into_iter().enumerate() {
+        match lockdowns.remove(id).await {
+            Ok(_) => removed.push(format!(":white_check_mark: `{}` ({})", typ, id)),
+            Err(e) => failed.push(format!(":x: `{}` ({}): {}", typ, id, e)),
+        }
+
+        // Progress updates are best-effort, a failed edit must not stop the remaining lockdowns from being removed
+        if let Err(e) = base_message
+            .edit(
+                ctx,
+                poise::CreateReply::new().embed(
+                    serenity::all::CreateEmbed::new()
+                        .title("Removing Lockdowns...")
+                        .description(format!(
+                            ":yellow_circle: Removing {}/{} lockdowns",
+                            i + 1,
+                            total
+                        )),
+                ),
+            )
+            .await
+        {
+            log::warn!("Failed to update lockdown removal progress: {}", e);
+        }
+    }
+
+    if !removed.is_empty() {
+        record_snapshot_after_remove(&ctx, guild_id, lockdowns.lockdowns().is_empty()).await;
+    }
+
+    let mut msg = removed.join("\n");
+
+    if !failed.is_empty() {
+        if !msg.is_empty() {
+            msg.push_str("\n\n");
+        }
+
+        msg.push_str("**Could not be reverted:**\n");
+        msg.push_str(&failed.join("\n"));
+    }
+
+    msg = truncate(&msg, 4000);
+
+    base_message
+        .edit(
+            ctx,
+            poise::CreateReply::new().embed(
+                serenity::all::CreateEmbed::new()
+                    .title(format!("Removed {}/{} Lockdowns", removed.len(), total))
+                    .description(msg)
+                    .color(if failed.is_empty() {
+                        serenity::all::Colour::DARK_GREEN
+                    } else {
+                        serenity::all::Colour::RED
+                    }),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "verify")]
+/// Checks that channel overwrites and role permissions still match the active lockdowns
+pub async fn lockdowns_verify(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "lockdowns.verify".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    // Get the current lockdown set
+    let lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            ctx.serenity_context().cache.clone(),
+            ctx.serenity_context().http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+    // With active lockdowns, compare against the state after the last apply/remove. Otherwise,
+    // compare against the state before the lockdowns were applied to find anything left locked
+    let kind = if lockdowns.lockdowns().is_empty() {
+        SnapshotKind::Baseline
+    } else {
+        SnapshotKind::Expected
+    };
+
+    let Some(stored) = lockdown_snapshots::load(&data.pool, guild_id, kind).await? else {
+        return Err(match kind {
+            SnapshotKind::Baseline => "No active lockdowns and no previous lockdown state to compare against",
+            SnapshotKind::Expected => "No recorded state for the active lockdowns. Lockdowns applied outside of this bot cannot be verified",
+        }
+        .into());
+    };
+
+    // Only channels and roles managed by the lockdowns are verified. Once all lockdowns are removed,
+    // this is whatever they had changed from the baseline as of the last recorded lockdown state
+    let scope = match kind {
+        SnapshotKind::Baseline => {
+            let Some(last_locked) =
+                lockdown_snapshots::load(&data.pool, guild_id, SnapshotKind::Expected).await?
+            else {
+                return Err(
+                    "No record of what the previous lockdowns changed to compare against".into(),
+                );
+            };
+
+            ManagedScope::changed_between(&stored.snapshot, &last_locked.snapshot)
+        }
+        SnapshotKind::Expected => {
+            ManagedScope::from_lockdowns(lockdowns.lockdowns(), stored.created_at).await?
+        }
+    };
+
+    // Lockdowns applied after the recorded state (e.g. by a template or the website) are not part of it,
+    // so repairing would undo them. Only accepting the current permissions is allowed then
+    let stale = kind == SnapshotKind::Expected && stored.is_stale(lockdowns.lockdowns());
+    let expected = stored.snapshot;
+
+    ctx.defer().await?;
+
+    let actual = lockdown_snapshots::capture(ctx.http(), guild_id).await?;
+    let drifts = lockdown_snapshots::diff(&expected, &actual, &scope);
+
+    if drifts.is_empty() && !stale {
+        ctx.send(
+            poise::CreateReply::new().embed(
+                CreateEmbed::new()
+                    .title("Lockdowns Verified")
+                    .description(match kind {
+                        SnapshotKind::Baseline => ":white_check_mark: All permissions were restored after the last lockdown",
+                        SnapshotKind::Expected => ":white_check_mark: All permissions match the active lockdowns",
+                    })
+                    .color(serenity::all::Colour::DARK_GREEN),
+            ),
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let mut report = drifts
+        .iter()
+        .map(|d| format!("- {}", d))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    report = truncate(&report, 3500);
+
+    let msg = ctx
+        .send(
+            poise::CreateReply::new()
+                .embed(
+                    CreateEmbed::new()
+                        .title(if stale {
+                            "Lockdown State Out Of Date".to_string()
+                        } else {
+                            format!("{} Permission Differences Found", drifts.len())
+                        })
+                        .description(format!(
+                            "{}\n\n{}",
+                            match kind {
+                                _ if stale => "A lockdown was applied after AntiRaid last recorded the lockdown state, so the newer lockdowns cannot be verified and nothing can be repaired. **Accept** records the current permissions as the lockdown state. Differences for the older lockdowns are listed below.",
+                                SnapshotKind::Baseline => "The following permissions differ from before the last lockdown was applied. **Repair** restores them to their pre-lockdown state.",
+                                SnapshotKind::Expected => "The following permissions differ from the active lockdowns. **Repair** re-applies the lockdown state, **Accept** keeps the current permissions as the new lockdown state.",
+                            },
+                            report
+                        ))
+                        .color(serenity::all::Colour::RED),
+                )
+                .components(vec![CreateActionRow::buttons(vec![
+                    CreateButton::new("lockdowns_verify_repair")
+                        .label("Repair")
+                        .style(serenity::all::ButtonStyle::Danger)
+                        .disabled(stale),
+                    CreateButton::new("lockdowns_verify_accept")
+                        .label("Accept")
+                        .style(serenity::all::ButtonStyle::Primary),
+                    CreateButton::new("lockdowns_verify_cancel")
+                        .label("Cancel")
+                        .style(serenity::all::ButtonStyle::Secondary),
+                ])]),
+        )
+        .await?
+        .into_message()
+        .await?;
+
+    let Some(item) = msg
+        .id
+        .await_component_interaction(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(120))
+        .await
+    else {
+        return Ok(());
+    };
+
+    match item.data.custom_id.as_str() {
+        "lockdowns_verify_repair" if !stale => {
+            item.defer(ctx.http()).await?;
+
+            let total = drifts.len();
+            let failed = lockdown_snapshots::repair(
+                ctx.http(),
+                guild_id,
+                drifts,
+                &format!("Lockdown verification repair by {}", ctx.author().id),
+            )
+            .await;
+
+            let mut desc = format!(
+                ":white_check_mark: Repaired {}/{} differences",
+                total - failed.len(),
+                total
+            );
+
+            for (drift, err) in failed.iter() {
+                desc.push_str(&format!("\n:x: {}: {}", drift, err));
+            }
+
+            desc = truncate(&desc, 4000);
+
+            // Repairing to the baseline means the lockdown has now been fully lifted
+            if kind == SnapshotKind::Baseline && failed.is_empty() {
+                lockdown_snapshots::delete(&data.pool, guild_id, SnapshotKind::Baseline).await?;
+            }
+
+            item.create_followup(
+                ctx.http(),
+                serenity::all::CreateInteractionResponseFollowup::new().embed(
+                    CreateEmbed::new()
+                        .title("Lockdown Repair")
+                        .description(desc),
+                ),
+            )
+            .await?;
+        }
+        "lockdowns_verify_accept" => {
+            match kind {
+                SnapshotKind::Baseline => {
+                    lockdown_snapshots::delete(&data.pool, guild_id, SnapshotKind::Baseline).await?
+                }
+                SnapshotKind::Expected => {
+                    lockdown_snapshots::save(&data.pool, guild_id, SnapshotKind::Expected, &actual)
+                        .await?
+                }
+            }
+
+            item.create_response(
+                ctx.http(),
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new()
+                        .content("Current permissions accepted"),
+                ),
+            )
+            .await?;
+        }
+        _ => {
+            item.create_response(
+                ctx.http(),
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new()
+                        .content("Cancelled successfully!"),
+                ),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "autoverify")]
+/// Configures periodic verification of active lockdowns
+pub async fn lockdowns_autoverify(
+    ctx: Context<'_>,
+    #[description = "Whether periodic verification is enabled"] enabled: bool,
+    #[description = "Channel to send drift alerts to. Defaults to the current channel"]
+    channel: Option<serenity::all::ChannelId>,
+    #[description = "Whether to automatically repair drift. Defaults to false"] auto_repair: Option<
+        bool,
+    >,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "lockdowns.autoverify".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    if !enabled {
+        sqlx::query("DELETE FROM lockdown__autoverify WHERE guild_id = $1")
+            .bind(guild_id.to_string())
+            .execute(&data.pool)
+            .await?;
+
+        ctx.say("Periodic lockdown verification disabled").await?;
+        return Ok(());
+    }
+
+    let channel = channel.unwrap_or(ctx.channel_id());
+
+    sqlx::query(
+        "INSERT INTO lockdown__autoverify (guild_id, alert_channel, auto_repair) VALUES ($1, $2, $3) ON CONFLICT (guild_id) DO UPDATE SET alert_channel = EXCLUDED.alert_channel, auto_repair = EXCLUDED.auto_repair, last_report = NULL",
+    )
+    .bind(guild_id.to_string())
+    .bind(channel.to_string())
+    .bind(auto_repair.unwrap_or(false))
+    .execute(&data.pool)
+    .await?;
+
+    ctx.say(format!(
+        "Periodic lockdown verification enabled. Alerts will be sent to <#{}>",
+        channel
+    ))
+    .await?;
+
+    Ok(())
+}
@@ -2,13 +2,15 @@ use std::vec;
 
 use crate::config::CONFIG;
 
+mod antiraid;
 mod backups;
 mod help;
 mod load;
-mod lockdowns;
+pub(crate) mod lockdowns;
 mod moderation;
 mod ping;
 mod stats;
+mod templates;
 mod whois;
 
 pub fn command_permissions_metadata() -> indexmap::IndexMap<String, Vec<String>> {
@@ -24,12 +26,36 @@ pub fn command_permissions_metadata() -> indexmap::IndexMap<String, Vec<String>>
         "lockdowns qsl".to_string() => vec!["lockdowns.qsl".to_string()],
         "lockdowns scl".to_string() => vec!["lockdowns.scl".to_string()],
         "lockdowns role".to_string() => vec!["lockdowns.role".to_string()],
+        "lockdowns vcl".to_string() => vec!["lockdowns.vcl".to_string()],
         "lockdowns remove".to_string() => vec!["lockdowns.remove".to_string()],
+        "lockdowns remove_all".to_string() => vec!["lockdowns.remove_all".to_string()],
+        "lockdowns verify".to_string() => vec!["lockdowns.verify".to_string()],
+        "lockdowns autoverify".to_string() => vec!["lockdowns.autoverify".to_string()],
+        "antiraid panic".to_string() => vec!["antiraid.panic".to_string()],
+        "antiraid calm".to_string() => vec!["antiraid.calm".to_string()],
         "backups create".to_string() => vec!["backups.create".to_string()],
         "backups list".to_string() => vec!["backups.list".to_string()],
         "backups delete".to_string() => vec!["backups.delete".to_string()],
         "backups restore".to_string() => vec!["backups.restore".to_string()],
+        "backups restore_messages".to_string() => vec!["backups.restore".to_string()],
+        "backups pin".to_string() => vec!["backups.pin".to_string()],
+        "backups unpin".to_string() => vec!["backups.pin".to_string()],
+        "backups verify".to_string() => vec!["backups.list".to_string()],
+        "backups usage".to_string() => vec!["backups.list".to_string()],
+        "backups diff".to_string() => vec!["backups.list".to_string()],
+        "backups schedule set".to_string() => vec!["backups.schedule".to_string()],
+        "backups schedule view".to_string() => vec!["backups.list".to_string()],
+        "backups schedule delete".to_string() => vec!["backups.schedule".to_string()],
         "load".to_string() => vec!["bot.load".to_string()],
+        "templates list".to_string() => vec!["templates.list".to_string()],
+        "templates shop".to_string() => vec!["templates.shop".to_string()],
+        "templates unload".to_string() => vec!["templates.unload".to_string()],
+        "templates upgrade".to_string() => vec!["templates.upgrade".to_string()],
+        "templates set_error_channel".to_string() => vec!["templates.set_error_channel".to_string()],
+        "templates fire_event".to_string() => vec!["templates.fire_event".to_string()],
+        "templates export".to_string() => vec!["templates.export".to_string()],
+        "templates import".to_string() => vec!["templates.import".to_string()],
+        "templates errors".to_string() => vec!["templates.errors".to_string()],
     }
 }
 
@@ -41,8 +67,10 @@ pub fn raw_commands() -> Vec<crate::Command> {
         whois::whois(),
         moderation::moderation(),
         lockdowns::lockdowns(),
+        antiraid::antiraid(),
         backups::backups(),
         load::load(),
+        templates::templates(),
     ]
 }
 
@@ -54,8 +82,10 @@ pub fn commands_to_register() -> Vec<serenity::all::CreateCommand<'static>> {
         whois::whois(),
         moderation::moderation(),
         lockdowns::lockdowns(),
+        antiraid::antiraid(),
         backups::backups(),
         load::load(),
+        templates::templates(),
     ]);
 
     commands_initial
@@ -0,0 +1,2174 @@
+use antiraid_types::ar_event::{AntiraidEvent, ModerationAction, ModerationStartEventData};
+use futures_util::StreamExt;
+use indexmap::IndexMap;
+use serenity::all::{
+    ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateButton, CreateEmbed,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, GuildId,
+};
+use silverpelt::ar_event::AntiraidEventOperations;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::bot::load::load_template;
+use crate::bot::template_dispatch_data;
+use crate::botlib::durationstring::parse_duration_string;
+use crate::botlib::templatecaps::{field_value, is_dangerous, CapabilityDiff};
+use crate::botlib::templateconfig::{self, ConfigField, ConfigFieldKind};
+use crate::botlib::templateerrors;
+use crate::botlib::templateexport::{TemplateExport, EXPORT_FORMAT_VERSION, MAX_EXPORT_SIZE};
+use crate::botlib::templateversions::{resolve_version, sort_versions};
+use crate::botlib::text::truncate;
+use crate::{Context, Error};
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ShopVersion {
+    pub name: String,
+    pub version: String,
+}
+
+pub(super) async fn shop_versions(pool: &sqlx::PgPool) -> Result<Vec<ShopVersion>, Error> {
+    Ok(sqlx::query_as("SELECT name, version FROM template_shop")
+        .fetch_all(pool)
+        .await?)
+}
+
+/// Returns the shop template name and version an installed template was loaded from, if any
+pub(super) fn match_shop_template(
+    versions: &[ShopVersion],
+    installed: &str,
+) -> Option<(String, String)> {
+    for v in versions {
+        if silverpelt::templates::create_shop_template(&v.name, &v.version) == installed {
+            return Some((v.name.clone(), v.version.clone()));
+        }
+
+        if silverpelt::templates::create_shop_template(&v.name, "latest") == installed {
+            return Some((v.name.clone(), "latest".to_string()));
+        }
+    }
+
+    None
+}
+
+/// Formats an installed shop template version for display, `latest` is shown as-is
+fn display_version(version: &str) -> String {
+    if version == "latest" {
+        version.to_string()
+    } else {
+        format!("v{}", version)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct InstalledTemplate {
+    pub name: String,
+    pub events: Vec<String>,
+    pub allowed_caps: Vec<String>,
+}
+
+/// Returns the installed template of a guild loaded from the given shop template, if any
+pub(super) async fn installed_shop_template(
+    pool: &sqlx::PgPool,
+    guild_id: GuildId,
+    versions: &[ShopVersion],
+    shop_name: &str,
+) -> Result<Option<InstalledTemplate>, Error> {
+    let installed: Vec<InstalledTemplate> = sqlx::query_as(
+        "SELECT name, events, allowed_caps FROM guild_templates WHERE guild_id = $1",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(installed.into_iter().find(|t| {
+        match_shop_template(versions, &t.name).is_some_and(|(name, _)| name == shop_name)
+    }))
+}
+
+/// Shows the capability and event changes of a template load or upgrade and waits for the user to confirm
+///
+/// Capabilities can be denied through a select menu before confirming. Returns the confirming
+/// interaction and the capabilities to grant, or `None` if the user cancelled
+pub(super) async fn confirm_capabilities(
+    ctx: &Context<'_>,
+    embed: CreateEmbed<'_>,
+    diff: &CapabilityDiff,
+) -> Result<Option<(ComponentInteraction, Vec<String>)>, Error> {
+    /// Size of each diff field. The five of them stay well under Discord's 6000 character embed limit, leaving
+    /// the rest to the caller's fields
+    const DIFF_FIELD_MAX: usize = 600;
+
+    /// Discord allows at most 5 action rows, one of which holds the buttons
+    const MAX_DENY_MENUS: usize = 4;
+
+    let requested = diff.requested_caps();
+
+    // Select menus allow at most 25 options each, capabilities beyond what the menus can show could not be denied
+    if requested.len() > MAX_DENY_MENUS * 25 {
+        return Err(format!(
+            "This template asks for {} capabilities, at most {} are supported",
+            requested.len(),
+            MAX_DENY_MENUS * 25
+        )
+        .into());
+    }
+
+    let mut embed = embed
+        .field(
+            "New Capabilities",
+            field_value(&diff.added_caps, true, DIFF_FIELD_MAX),
+            false,
+        )
+        .field(
+            "Dropped Capabilities",
+            field_value(&diff.removed_caps, false, DIFF_FIELD_MAX),
+            false,
+        );
+
+    if !diff.kept_caps.is_empty() {
+        embed = embed.field(
+            "Unchanged Capabilities",
+            field_value(&diff.kept_caps, true, DIFF_FIELD_MAX),
+            false,
+        );
+    }
+
+    embed = embed
+        .field(
+            "Events Added",
+            field_value(&diff.added_events, false, DIFF_FIELD_MAX),
+            true,
+        )
+        .field(
+            "Events Removed",
+            field_value(&diff.removed_events, false, DIFF_FIELD_MAX),
+            true,
+        );
+
+    if requested.iter().any(|c| is_dangerous(c)) {
+        embed = embed.footer(serenity::all::CreateEmbedFooter::new(
+            "⚠️ marks capabilities that can take destructive or moderation actions. Use the menu below to deny any you do not want to grant",
+        ));
+    }
+
+    let mut components = Vec::new();
+
+    // List dangerous capabilities first
+    let mut options_caps = requested.clone();
+    options_caps.sort_by_key(|c| !is_dangerous(c));
+
+    let menus = options_caps.chunks(25).count();
+
+    for (i, chunk) in options_caps.chunks(25).enumerate() {
+        let options = chunk
+            .iter()
+            .map(|cap| {
+                let option = CreateSelectMenuOption::new(cap.clone(), cap.clone());
+
+                if is_dangerous(cap) {
+                    option.description("Dangerous capability")
+                } else {
+                    option
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let max_values = options.len() as u8;
+
+        components.push(CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                format!("deny_caps_{}", i),
+                CreateSelectMenuKind::String {
+                    options: options.into(),
+                },
+            )
+            .placeholder(if menus > 1 {
+                format!("Capabilities to deny ({} of {})", i + 1, menus)
+            } else {
+                "Capabilities to deny".to_string()
+            })
+            .min_values(0)
+            .max_values(max_values),
+        ));
+    }
+
+    components.push(CreateActionRow::buttons(vec![
+        CreateButton::new("yes")
+            .label("Yes")
+            .style(serenity::all::ButtonStyle::Danger),
+        CreateButton::new("no")
+            .label("No")
+            .style(serenity::all::ButtonStyle::Primary),
+    ]));
+
+    let msg = ctx
+        .send(
+            poise::CreateReply::new()
+                .embed(embed)
+                .components(components),
+        )
+        .await?
+        .into_message()
+        .await?;
+
+    let mut collect_stream = msg
+        .id
+        .await_component_interactions(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(300))
+        .stream();
+
+    // Denied capabilities of each select menu
+    let mut denied: HashMap<String, Vec<String>> = HashMap::new();
+
+    while let Some(item) = collect_stream.next().await {
+        match item.data.custom_id.as_str() {
+            id if id.starts_with("deny_caps_") => {
+                if let ComponentInteractionDataKind::StringSelect { ref values, .. } =
+                    item.data.kind
+                {
+                    denied.insert(
+                        id.to_string(),
+                        values.iter().map(|v| v.to_string()).collect(),
+                    );
+                }
+
+                item.defer(&ctx.serenity_context().http).await?;
+            }
+            "yes" => {
+                let granted = requested
+                    .into_iter()
+                    .filter(|c| !denied.values().any(|d| d.contains(c)))
+                    .collect();
+
+                return Ok(Some((item, granted)));
+            }
+            _ => {
+                item.create_response(
+                    ctx.http(),
+                    serenity::all::CreateInteractionResponse::Message(
+                        serenity::all::CreateInteractionResponseMessage::new()
+                            .content("Cancelled successfully!"),
+                    ),
+                )
+                .await?;
+
+                return Ok(None);
+            }
+        }
+    }
+
+    Err("No response".into())
+}
+
+/// Renders one step of the template configuration prompt
+fn create_config_reply<'a>(
+    steps: &[Vec<&ConfigField>],
+    step: usize,
+    values: &serde_json::Map<String, serde_json::Value>,
+    error: Option<&str>,
+) -> poise::CreateReply<'a> {
+    let fields = &steps[step];
+    let is_select = fields[0].is_select();
+
+    let mut description = format!(
+        "Step {} of {}. {}",
+        step + 1,
+        steps.len(),
+        if is_select {
+            "Pick a value for each setting below"
+        } else {
+            "Press **Enter Values** to fill in the settings below"
+        }
+    );
+
+    if let Some(error) = error {
+        description += &format!("\n\n:x: **Error**: {}", error);
+    }
+
+    let mut embed = CreateEmbed::default()
+        .title("Configure Template")
+        .description(description)
+        .color(if error.is_some() {
+            serenity::all::Colour::RED
+        } else {
+            serenity::all::Colour::BLURPLE
+        });
+
+    for field in fields {
+        let current = match values.get(&field.id).or(field.default.as_ref()) {
+            Some(value) => field.display(value),
+            None => "Not set".to_string(),
+        };
+
+        embed = embed.field(
+            format!("{}{}", field.label, if field.required { " *" } else { "" }),
+            truncate(
+                &format!(
+                    "{}\n**Current**: {}",
+                    field.description.as_deref().unwrap_or_default(),
+                    current
+                ),
+                1024,
+            ),
+            false,
+        );
+    }
+
+    let mut components = Vec::new();
+
+    if is_select {
+        for (i, field) in fields.iter().enumerate() {
+            let kind = match field.kind {
+                ConfigFieldKind::Channel => CreateSelectMenuKind::Channel {
+                    channel_types: None,
+                    default_channels: None,
+                },
+                ConfigFieldKind::Role => CreateSelectMenuKind::Role {
+                    default_roles: None,
+                },
+                ConfigFieldKind::Choice { ref choices } => CreateSelectMenuKind::String {
+                    options: choices
+                        .iter()
+                        .map(|c| CreateSelectMenuOption::new(c.clone(), c.clone()))
+                        .collect::<Vec<_>>()
+                        .into(),
+                },
+                _ => CreateSelectMenuKind::String {
+                    options: vec![
+                        CreateSelectMenuOption::new("True", "true"),
+                        CreateSelectMenuOption::new("False", "false"),
+                    ]
+                    .into(),
+                },
+            };
+
+            components.push(CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(format!("tcfg:{}", i), kind)
+                    .placeholder(field.label.clone())
+                    .min_values(0)
+                    .max_values(1),
+            ));
+        }
+    }
+
+    let mut buttons = Vec::new();
+
+    if !is_select {
+        buttons.push(
+            CreateButton::new("tcfg_modal")
+                .label("Enter Values")
+                .style(serenity::all::ButtonStyle::Primary),
+        );
+    }
+
+    buttons.push(
+        CreateButton::new("tcfg_next")
+            .label(if step + 1 == steps.len() {
+                "Finish"
+            } else {
+                "Next"
+            })
+            .style(serenity::all::ButtonStyle::Success),
+    );
+    buttons.push(
+        CreateButton::new("tcfg_cancel")
+            .label("Cancel")
+            .style(serenity::all::ButtonStyle::Danger),
+    );
+
+    components.push(CreateActionRow::buttons(buttons));
+
+    poise::CreateReply::default()
+        .embed(embed)
+        .components(components)
+}
+
+/// Collects the configuration of a template from the user according to its config schema
+///
+/// Text and number settings are entered through modals (at most 5 per modal) while channels, roles,
+/// booleans and choices use select menus (at most 4 per message). Returns `None` if the user cancelled
+pub(super) async fn collect_template_config(
+    ctx: &Context<'_>,
+    schema: &[ConfigField],
+) -> Result<Option<serde_json::Value>, Error> {
+    let mut steps: Vec<Vec<&ConfigField>> = Vec::new();
+    for field in schema {
+        let max = if field.is_select() { 4 } else { 5 };
+
+        match steps.last_mut() {
+            Some(step) if step[0].is_select() == field.is_select() && step.len() < max => {
+                step.push(field)
+            }
+            _ => steps.push(vec![field]),
+        }
+    }
+
+    if steps.is_empty() {
+        return Ok(Some(serde_json::Value::Object(serde_json::Map::new())));
+    }
+
+    let mut step = 0;
+    let mut values = serde_json::Map::new();
+
+    let handle = ctx
+        .send(create_config_reply(&steps, step, &values, None))
+        .await?;
+
+    let msg = handle.message().await?;
+
+    let mut collect_stream = msg
+        .id
+        .await_component_interactions(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(600))
+        .stream();
+
+    while let Some(item) = collect_stream.next().await {
+        let mut error = None;
+
+        match item.data.custom_id.as_str() {
+            "tcfg_modal" => {
+                let mut modal = serenity::all::CreateQuickModal::new("Configure Template")
+                    .timeout(Duration::from_secs(300));
+
+                for field in &steps[step] {
+                    // Modal labels are limited to 45 characters
+                    modal = modal.short_field(field.label.chars().take(45).collect::<String>());
+                }
+
+                let Some(resp) = item.quick_modal(ctx.serenity_context(), modal).await? else {
+                    continue;
+                };
+
+                resp.interaction
+                    .create_response(
+                        ctx.http(),
+                        serenity::all::CreateInteractionResponse::Acknowledge,
+                    )
+                    .await?;
+
+                let mut errors = Vec::new();
+                for (field, raw) in steps[step].iter().zip(resp.inputs.iter()) {
+                    if raw.trim().is_empty() {
+                        values.remove(&field.id);
+                        continue;
+                    }
+
+                    match field.parse(raw) {
+                        Ok(value) => {
+                            values.insert(field.id.clone(), value);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+
+                if !errors.is_empty() {
+                    error = Some(errors.join("\n"));
+                }
+            }
+            "tcfg_next" => {
+                item.defer(&ctx.serenity_context().http).await?;
+
+                if let Some(missing) = steps[step]
+                    .iter()
+                    .find(|f| f.required && f.default.is_none() && !values.contains_key(&f.id))
+                {
+                    error = Some(format!("{} is required", missing.label));
+                } else if step + 1 < steps.len() {
+                    step += 1;
+                } else {
+                    match templateconfig::validate(schema, &values) {
+                        Ok(config) => {
+                            handle
+                                .edit(
+                                    *ctx,
+                                    poise::CreateReply::default()
+                                        .content("Template configuration saved")
+                                        .embeds(vec![])
+                                        .components(vec![]),
+                                )
+                                .await?;
+
+                            return Ok(Some(config));
+                        }
+                        Err(e) => error = Some(e),
+                    }
+                }
+            }
+            "tcfg_cancel" => {
+                item.defer(&ctx.serenity_context().http).await?;
+
+                handle
+                    .edit(
+                        *ctx,
+                        poise::CreateReply::default()
+                            .content("Cancelled successfully!")
+                            .embeds(vec![])
+                            .components(vec![]),
+                    )
+                    .await?;
+
+                return Ok(None);
+            }
+            id => {
+                item.defer(&ctx.serenity_context().http).await?;
+
+                let Some(field) = id
+                    .strip_prefix("tcfg:")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .and_then(|i| steps[step].get(i))
+                else {
+                    continue;
+                };
+
+                let raw = match item.data.kind {
+                    ComponentInteractionDataKind::StringSelect {
+                        values: ref selected,
+                        ..
+                    } => selected.first().map(|v| v.to_string()),
+                    ComponentInteractionDataKind::ChannelSelect {
+                        values: ref selected,
+                        ..
+                    } => selected.first().map(|v| v.to_string()),
+                    ComponentInteractionDataKind::RoleSelect {
+                        values: ref selected,
+                        ..
+                    } => selected.first().map(|v| v.to_string()),
+                    _ => None,
+                };
+
+                match raw {
+                    Some(raw) => match field.parse(&raw) {
+                        Ok(value) => {
+                            values.insert(field.id.clone(), value);
+                        }
+                        Err(e) => error = Some(e),
+                    },
+                    None => {
+                        values.remove(&field.id);
+                    }
+                }
+            }
+        }
+
+        handle
+            .edit(
+                *ctx,
+                create_config_reply(&steps, step, &values, error.as_deref()),
+            )
+            .await?;
+    }
+
+    Err("No response".into())
+}
+
+/// Returns the templates to name in the `OnStartup` event sent after the templates of a guild changed
+///
+/// The template worker has no event for stopping a template, `OnStartup` is the only event that makes it
+/// re-read `guild_templates`. Naming only the changed templates is enough when nothing was removed, but a
+/// removed template would keep running in the worker until it restarts. So when any template was removed,
+/// every template still installed is named instead, and the worker (which lives outside this repository)
+/// replaces its view of the guild with them.
+/// Removed templates are never named, as the worker would fail to find their rows
+pub(crate) fn startup_templates(
+    changed: Vec<String>,
+    removed: &[String],
+    installed: Vec<String>,
+) -> Vec<String> {
+    if removed.is_empty() {
+        return changed;
+    }
+
+    installed
+        .into_iter()
+        .filter(|name| !removed.contains(name))
+        .collect()
+}
+
+/// Tells the template worker that templates of a guild were added, changed or removed
+///
+/// See [`startup_templates`] for what is sent
+pub(crate) async fn dispatch_template_changes(
+    data: &silverpelt::data::Data,
+    guild_id: GuildId,
+    changed: Vec<String>,
+    removed: &[String],
+) -> Result<(), Error> {
+    let installed = if removed.is_empty() {
+        Vec::new()
+    } else {
+        #[derive(sqlx::FromRow)]
+        struct InstalledRecord {
+            name: String,
+        }
+
+        let rows: Vec<InstalledRecord> =
+            sqlx::query_as("SELECT name FROM guild_templates WHERE guild_id = $1")
+                .bind(guild_id.to_string())
+                .fetch_all(&data.pool)
+                .await?;
+
+        rows.into_iter().map(|r| r.name).collect()
+    };
+
+    AntiraidEvent::OnStartup(startup_templates(changed, removed, installed))
+        .dispatch_to_template_worker_and_nowait(data, guild_id, &template_dispatch_data())
+        .await
+        .map_err(|e| format!("Failed to dispatch OnStartup event: {:?}", e))?;
+
+    Ok(())
+}
+
+pub async fn installed_template_autocomplete<'a>(
+    ctx: Context<'_>,
+    partial: &str,
+) -> serenity::all::CreateAutocompleteResponse<'a> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return serenity::all::CreateAutocompleteResponse::new();
+    };
+
+    #[derive(sqlx::FromRow)]
+    struct TemplateRecord {
+        name: String,
+    }
+
+    match sqlx::query_as(
+        "SELECT name FROM guild_templates WHERE guild_id = $1 AND name ILIKE $2 LIMIT 25",
+    )
+    .bind(guild_id.to_string())
+    .bind(format!(
+        "%{}%",
+        partial.replace('%', "\\%").replace('_', "\\_")
+    ))
+    .fetch_all(&ctx.data().pool)
+    .await
+    {
+        Ok(templates) => {
+            let templates: Vec<TemplateRecord> = templates;
+            let mut choices = serenity::all::CreateAutocompleteResponse::new();
+
+            for template in templates {
+                choices = choices.add_choice(serenity::all::AutocompleteChoice::new(
+                    template.name.clone(),
+                    template.name,
+                ));
+            }
+
+            choices
+        }
+        Err(e) => {
+            log::error!("Failed to fetch guild templates: {:?}", e);
+            serenity::all::CreateAutocompleteResponse::new()
+        }
+    }
+}
+
+/// Manage the templates loaded on this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "templates_list",
+        "templates_shop",
+        "templates_unload",
+        "templates_upgrade",
+        "templates_set_error_channel",
+        "templates_fire_event",
+        "templates_export",
+        "templates_import",
+        "templates_errors"
+    )
+)]
+pub async fn templates(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Lists the templates loaded on this server
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "list")]
+pub async fn templates_list(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.list".into(),
+    )
+    .await?;
+
+    #[derive(sqlx::FromRow)]
+    struct TemplateRecord {
+        name: String,
+        events: Vec<String>,
+        allowed_caps: Vec<String>,
+        error_channel: Option<String>,
+        created_by: String,
+    }
+
+    let templates: Vec<TemplateRecord> = sqlx::query_as(
+        "SELECT name, events, allowed_caps, error_channel, created_by FROM guild_templates WHERE guild_id = $1 ORDER BY name",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(&ctx.data().pool)
+    .await?;
+
+    if templates.is_empty() {
+        ctx.say("No templates are loaded on this server. Use `/load` to load one")
+            .await?;
+        return Ok(());
+    }
+
+    let versions = shop_versions(&ctx.data().pool).await?;
+
+    /// Number of templates shown per page, kept low enough for the embed to stay under 6000 characters
+    const PAGE_SIZE: usize = 4;
+
+    let pages = templates.len().div_ceil(PAGE_SIZE);
+
+    let create_reply = |page: usize| {
+        let mut embed = CreateEmbed::default()
+            .title("Loaded Templates")
+            .description(format!("{} templates loaded", templates.len()))
+            .footer(serenity::all::CreateEmbedFooter::new(format!(
+                "Page {} of {}",
+                page + 1,
+                pages
+            )));
+
+        for template in templates.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+            let version = match match_shop_template(&versions, &template.name) {
+                Some((_, version)) => version,
+                None => "custom".to_string(),
+            };
+
+            let mut value = format!(
+                "**Version**: {}\n**Events**: `{}`\n**Capabilities**: `{}`\n**Error Channel**: {}\n**Created By**: <@{}>",
+                version,
+                template.events.join(", "),
+                template.allowed_caps.join(", "),
+                match template.error_channel {
+                    Some(ref channel) => format!("<#{}>", channel),
+                    None => "None".to_string(),
+                },
+                template.created_by
+            );
+
+            value = truncate(&value, 1024);
+
+            embed = embed.field(template.name.clone(), value, false);
+        }
+
+        poise::CreateReply::default()
+            .embed(embed)
+            .components(vec![CreateActionRow::buttons(vec![
+                CreateButton::new("list_previous")
+                    .label("Previous")
+                    .style(serenity::all::ButtonStyle::Primary)
+                    .disabled(page == 0),
+                CreateButton::new("list_next")
+                    .label("Next")
+                    .style(serenity::all::ButtonStyle::Primary)
+                    .disabled(page + 1 >= pages),
+            ])])
+    };
+
+    let mut page = 0;
+
+    let msg = ctx.send(create_reply(page)).await?.into_message().await?;
+
+    let mut collect_stream = msg
+        .id
+        .await_component_interactions(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(180))
+        .stream();
+
+    while let Some(item) = collect_stream.next().await {
+        match item.data.custom_id.as_str() {
+            "list_previous" => page = page.saturating_sub(1),
+            "list_next" => page = (page + 1).min(pages - 1),
+            _ => continue,
+        }
+
+        item.defer(&ctx.serenity_context().http).await?;
+
+        item.edit_response(
+            &ctx.serenity_context().http,
+            create_reply(page)
+                .to_slash_initial_response_edit(serenity::all::EditInteractionResponse::default()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// A template in the template shop, described by its newest version
+struct ShopListing {
+    name: String,
+    friendly_name: String,
+    description: String,
+    language: String,
+    events: Vec<String>,
+    allowed_caps: Vec<String>,
+    /// The version `load` picks for `latest`, which is what Install loads
+    latest: String,
+    /// All versions of the template, newest first
+    versions: Vec<String>,
+    /// The number of servers which have any version of the template loaded
+    installs: i64,
+}
+
+/// Returns all templates in the template shop, sorted by friendly name
+async fn shop_listings(pool: &sqlx::PgPool) -> Result<Vec<ShopListing>, Error> {
+    #[derive(sqlx::FromRow)]
+    struct ShopRecord {
+        name: String,
+        friendly_name: String,
+        version: String,
+        description: String,
+        events: Vec<String>,
+        language: String,
+        allowed_caps: Vec<String>,
+    }
+
+    #[derive(sqlx::FromRow)]
+    struct InstallRecord {
+        name: String,
+        count: i64,
+    }
+
+    let records: Vec<ShopRecord> = sqlx::query_as(
+        "SELECT name, friendly_name, version, description, events, language, allowed_caps FROM template_shop",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let installed: Vec<InstallRecord> =
+        sqlx::query_as("SELECT name, COUNT(*) AS count FROM guild_templates GROUP BY name")
+            .fetch_all(pool)
+            .await?;
+
+    let versions = records
+        .iter()
+        .map(|r| ShopVersion {
+            name: r.name.clone(),
+            version: r.version.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut installs: HashMap<String, i64> = HashMap::new();
+    for rec in installed {
+        if let Some((shop_name, _)) = match_shop_template(&versions, &rec.name) {
+            *installs.entry(shop_name).or_default() += rec.count;
+        }
+    }
+
+    let mut grouped: IndexMap<String, Vec<ShopRecord>> = IndexMap::new();
+    for rec in records {
+        grouped.entry(rec.name.clone()).or_default().push(rec);
+    }
+
+    let mut listings = Vec::new();
+    for (name, mut records) in grouped {
+        let mut template_versions = records
+            .iter()
+            .map(|r| r.version.clone())
+            .collect::<Vec<_>>();
+
+        sort_versions(&mut template_versions);
+
+        let newest = resolve_version(&template_versions, "latest")
+            .unwrap_or_else(|_| template_versions[0].clone());
+
+        let Some(pos) = records.iter().position(|r| r.version == newest) else {
+            continue;
+        };
+
+        let rec = records.swap_remove(pos);
+
+        listings.push(ShopListing {
+            installs: installs.get(&name).copied().unwrap_or_default(),
+            name,
+            friendly_name: rec.friendly_name,
+            description: rec.description,
+            language: rec.language,
+            events: rec.events,
+            allowed_caps: rec.allowed_caps,
+            latest: newest,
+            versions: template_versions,
+        });
+    }
+
+    listings.sort_by(|a, b| {
+        a.friendly_name
+            .to_lowercase()
+            .cmp(&b.friendly_name.to_lowercase())
+    });
+
+    Ok(listings)
+}
+
+/// Browse the templates available in the template shop
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "shop")]
+pub async fn templates_shop(
+    ctx: Context<'_>,
+    #[description = "Only show templates whose name or description contains this text"]
+    search: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.shop".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    let mut listings = shop_listings(&data.pool).await?;
+
+    if let Some(search) = search {
+        let search = search.to_lowercase();
+
+        listings.retain(|l| {
+            l.name.to_lowercase().contains(&search)
+                || l.friendly_name.to_lowercase().contains(&search)
+                || l.description.to_lowercase().contains(&search)
+        });
+    }
+
+    if listings.is_empty() {
+        ctx.say("No templates found in the template shop").await?;
+        return Ok(());
+    }
+
+    /// Number of templates shown per page
+    const PAGE_SIZE: usize = 5;
+
+    fn create_embed_for_listing<'a>(listing: &ShopListing) -> CreateEmbed<'a> {
+        CreateEmbed::default()
+            .title(listing.friendly_name.clone())
+            .description(truncate(&listing.description, 1024))
+            .field("Name", format!("`{}`", listing.name), true)
+            .field("Language", listing.language.clone(), true)
+            .field("Installs", listing.installs.to_string(), true)
+            .field(
+                "Version History",
+                field_value(&listing.versions, false, 1024),
+                true,
+            )
+            .field("Events", field_value(&listing.events, false, 1024), true)
+            .field(
+                "Capabilities",
+                field_value(&listing.allowed_caps, true, 1024),
+                false,
+            )
+            .color(serenity::all::Colour::BLURPLE)
+    }
+
+    fn create_reply<'a>(
+        listings: &[ShopListing],
+        page: usize,
+        selected: Option<usize>,
+    ) -> poise::CreateReply<'a> {
+        let pages = listings.len().div_ceil(PAGE_SIZE);
+        let start = page * PAGE_SIZE;
+        let on_page = &listings[start..(start + PAGE_SIZE).min(listings.len())];
+
+        let embed = match selected {
+            Some(i) => create_embed_for_listing(&listings[i]),
+            None => {
+                let mut embed = CreateEmbed::default()
+                    .title("Template Shop")
+                    .description(format!(
+                        "{} templates available. Pick one below to see its details and install it",
+                        listings.len()
+                    ))
+                    .footer(serenity::all::CreateEmbedFooter::new(format!(
+                        "Page {} of {}",
+                        page + 1,
+                        pages
+                    )))
+                    .color(serenity::all::Colour::BLURPLE);
+
+                for listing in on_page {
+                    let mut description = listing.description.clone();
+                    description = truncate(&description, 200);
+
+                    embed = embed.field(
+                        format!("{} (`{}`)", listing.friendly_name, listing.name),
+                        format!(
+                            "{}\n**Latest**: v{} | **Language**: {} | **Installs**: {}",
+                            description, listing.latest, listing.language, listing.installs
+                        ),
+                        false,
+                    );
+                }
+
+                embed
+            }
+        };
+
+        let options = on_page
+            .iter()
+            .enumerate()
+            .map(|(i, listing)| {
+                let mut label = listing.friendly_name.clone();
+                if label.chars().count() > 100 {
+                    label = label.chars().take(97).collect::<String>() + "...";
+                }
+
+                CreateSelectMenuOption::new(label, (start + i).to_string())
+                    .default_selection(selected == Some(start + i))
+            })
+            .collect::<Vec<_>>();
+
+        let mut buttons = vec![
+            CreateButton::new("shop_previous")
+                .label("Previous")
+                .style(serenity::all::ButtonStyle::Primary)
+                .disabled(page == 0),
+            CreateButton::new("shop_next")
+                .label("Next")
+                .style(serenity::all::ButtonStyle::Primary)
+                .disabled(page + 1 >= pages),
+        ];
+
+        if selected.is_some() {
+            buttons.push(
+                CreateButton::new("shop_back")
+                    .label("Back")
+                    .style(serenity::all::ButtonStyle::Secondary),
+            );
+            buttons.push(
+                CreateButton::new("shop_install")
+                    .label("Install")
+                    .style(serenity::all::ButtonStyle::Success),
+            );
+        }
+
+        poise::CreateReply::default()
+            .embed(embed)
+            .ephemeral(true)
+            .components(vec![
+                CreateActionRow::SelectMenu(
+                    CreateSelectMenu::new(
+                        "shop_select",
+                        CreateSelectMenuKind::String {
+                            options: options.into(),
+                        },
+                    )
+                    .placeholder("View template details"),
+                ),
+                CreateActionRow::buttons(buttons),
+            ])
+    }
+
+    let mut page = 0;
+    let mut selected: Option<usize> = None;
+
+    let msg = ctx
+        .send(create_reply(&listings, page, selected))
+        .await?
+        .into_message()
+        .await?;
+
+    let mut collect_stream = msg
+        .id
+        .await_component_interactions(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(180))
+        .stream();
+
+    while let Some(item) = collect_stream.next().await {
+        match item.data.custom_id.as_str() {
+            "shop_select" => {
+                if let ComponentInteractionDataKind::StringSelect { ref values, .. } =
+                    item.data.kind
+                {
+                    selected = values
+                        .first()
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .filter(|i| *i < listings.len());
+                }
+            }
+            "shop_previous" => {
+                page = page.saturating_sub(1);
+                selected = None;
+            }
+            "shop_next" => {
+                if (page + 1) * PAGE_SIZE < listings.len() {
+                    page += 1;
+                }
+                selected = None;
+            }
+            "shop_back" => {
+                selected = None;
+            }
+            "shop_install" => {
+                let Some(i) = selected else {
+                    continue;
+                };
+
+                if let Err(perm_res) = crate::botlib::permission_checks::check_permissions(
+                    guild_id,
+                    ctx.author().id,
+                    &data.pool,
+                    ctx.serenity_context(),
+                    &data.reqwest,
+                    &Some(ctx),
+                    "bot.load".into(),
+                )
+                .await
+                {
+                    item.create_response(
+                        &ctx.serenity_context().http,
+                        serenity::all::CreateInteractionResponse::Message(
+                            serenity::all::CreateInteractionResponseMessage::default()
+                                .ephemeral(true)
+                                .content(perm_res.to_string()),
+                        ),
+                    )
+                    .await?;
+
+                    continue;
+                }
+
+                item.defer(&ctx.serenity_context().http).await?;
+
+                load_template(&ctx, guild_id, &listings[i].name, None, "latest").await?;
+
+                continue;
+            }
+            _ => continue,
+        }
+
+        item.defer(&ctx.serenity_context().http).await?;
+
+        item.edit_response(
+            &ctx.serenity_context().http,
+            create_reply(&listings, page, selected)
+                .to_slash_initial_response_edit(serenity::all::EditInteractionResponse::default()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Unloads a template from this server
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "unload")]
+pub async fn templates_unload(
+    ctx: Context<'_>,
+    #[autocomplete = "installed_template_autocomplete"]
+    #[description = "The template to unload"]
+    name: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.unload".into(),
+    )
+    .await?;
+
+    let res = sqlx::query("DELETE FROM guild_templates WHERE guild_id = $1 AND name = $2")
+        .bind(guild_id.to_string())
+        .bind(&name)
+        .execute(&ctx.data().pool)
+        .await?;
+
+    if res.rows_affected() == 0 {
+        return Err("No template with that name is loaded on this server".into());
+    }
+
+    for table in ["templates__errors", "templates__error_alerts"] {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE guild_id = $1 AND template_name = $2",
+            table
+        ))
+        .bind(guild_id.to_string())
+        .bind(&name)
+        .execute(&ctx.data().pool)
+        .await?;
+    }
+
+    dispatch_template_changes(ctx.data(), guild_id, Vec::new(), &[name.clone()]).await?;
+
+    ctx.say(format!(
+        "Template `{}` unloaded successfully",
+        name.replace('`', "\\`")
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Upgrades a shop template to the newest version in the template shop
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "upgrade")]
+pub async fn templates_upgrade(
+    ctx: Context<'_>,
+    #[autocomplete = "installed_template_autocomplete"]
+    #[description = "The template to upgrade"]
+    name: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.upgrade".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    let versions = shop_versions(&data.pool).await?;
+
+    let Some((shop_name, current_version)) = match_shop_template(&versions, &name) else {
+        return Err("Only templates loaded from the template shop can be upgraded".into());
+    };
+
+    let newest_version = resolve_version(
+        &versions
+            .iter()
+            .filter(|v| v.name == shop_name)
+            .map(|v| v.version.clone())
+            .collect::<Vec<_>>(),
+        "latest",
+    )?;
+
+    // Templates loaded as `latest` are always pinned to the newest version so that its capabilities are reviewed
+    if current_version != "latest" && newest_version == current_version {
+        ctx.say(format!(
+            "`{}` is already on the newest version (v{})",
+            name.replace('`', "\\`"),
+            newest_version
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    #[derive(sqlx::FromRow)]
+    struct LatestRecord {
+        version: String,
+        events: Vec<String>,
+        allowed_caps: Vec<String>,
+        config_schema: serde_json::Value,
+        content: serde_json::Value,
+    }
+
+    let latest: LatestRecord = sqlx::query_as(
+        "SELECT version, events, allowed_caps, config_schema, content FROM template_shop WHERE name = $1 AND version = $2",
+    )
+    .bind(&shop_name)
+    .bind(&newest_version)
+    .fetch_one(&data.pool)
+    .await?;
+
+    #[derive(sqlx::FromRow)]
+    struct CurrentRecord {
+        events: Vec<String>,
+        allowed_caps: Vec<String>,
+        config: serde_json::Value,
+    }
+
+    let current: CurrentRecord = sqlx::query_as(
+        "SELECT events, allowed_caps, config FROM guild_templates WHERE guild_id = $1 AND name = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(&name)
+    .fetch_one(&data.pool)
+    .await?;
+
+    let schema = templateconfig::parse_schema(latest.config_schema)?;
+
+    let diff = CapabilityDiff::new(
+        Some((&current.allowed_caps, &current.events)),
+        &latest.allowed_caps,
+        &latest.events,
+    );
+
+    let Some((confirm, allowed_caps)) = confirm_capabilities(
+        &ctx,
+        CreateEmbed::default()
+            .title("Upgrade Template?")
+            .description(format!(
+                "Are you sure you want to upgrade `{}` from {} to v{}?",
+                shop_name.replace('`', "\\`"),
+                display_version(&current_version),
+                latest.version
+            )),
+        &diff,
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    // The existing configuration is kept unless the new version asks for settings it does not have
+    let existing = current.config.as_object().cloned().unwrap_or_default();
+    let (config, collected) = match templateconfig::validate(&schema, &existing) {
+        Ok(config) => (config, false),
+        Err(_) => {
+            confirm.defer(ctx.http()).await?;
+
+            let Some(config) = collect_template_config(&ctx, &schema).await? else {
+                return Ok(());
+            };
+
+            (config, true)
+        }
+    };
+
+    // The files of the new version are copied along with the configuration module, as in `load`
+    let content = if schema.is_empty() {
+        serde_json::Value::Null
+    } else {
+        templateconfig::with_config(latest.content, &config)?
+    };
+
+    let new_name = silverpelt::templates::create_shop_template(&shop_name, &latest.version);
+
+    sqlx::query(
+        "UPDATE guild_templates SET name = $3, events = $4, allowed_caps = $5, last_updated_by = $6, config = $7, content = $8 WHERE guild_id = $1 AND name = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(&name)
+    .bind(&new_name)
+    .bind(&latest.events)
+    .bind(&allowed_caps)
+    .bind(ctx.author().id.to_string())
+    .bind(&config)
+    .bind(&content)
+    .execute(&data.pool)
+    .await
+    .map_err(|e| format!("Failed to upgrade template: {:?}", e))?;
+
+    dispatch_template_changes(data, guild_id, vec![new_name], &[name.clone()]).await?;
+
+    let msg = format!(
+        "Template `{}` upgraded from {} to v{}",
+        shop_name.replace('`', "\\`"),
+        display_version(&current_version),
+        latest.version
+    );
+
+    if collected {
+        ctx.say(msg).await?;
+    } else {
+        confirm
+            .create_response(
+                ctx.http(),
+                serenity::all::CreateInteractionResponse::Message(
+                    serenity::all::CreateInteractionResponseMessage::new().content(msg),
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Changes the channel a template sends its errors to
+#[poise::command(
+    slash_command,
+    guild_only,
+    user_cooldown = "5",
+    rename = "set_error_channel"
+)]
+pub async fn templates_set_error_channel(
+    ctx: Context<'_>,
+    #[autocomplete = "installed_template_autocomplete"]
+    #[description = "The template to change the error channel of"]
+    name: String,
+    #[description = "Channel to send errors to"] error_channel: serenity::all::GuildChannel,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.set_error_channel".into(),
+    )
+    .await?;
+
+    let res = sqlx::query(
+        "UPDATE guild_templates SET error_channel = $3, last_updated_by = $4 WHERE guild_id = $1 AND name = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(&name)
+    .bind(error_channel.id.to_string())
+    .bind(ctx.author().id.to_string())
+    .execute(&ctx.data().pool)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Err("No template with that name is loaded on this server".into());
+    }
+
+    dispatch_template_changes(ctx.data(), guild_id, vec![name.clone()], &[]).await?;
+
+    ctx.say(format!(
+        "Errors from `{}` will now be sent to <#{}>",
+        name.replace('`', "\\`"),
+        error_channel.id
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Moderation actions `fire_event` can build a `ModerationStart` event for
+const MODERATION_PRESETS: &[&str] = &[
+    "moderation:kick",
+    "moderation:ban",
+    "moderation:tempban",
+    "moderation:timeout",
+    "moderation:unban",
+];
+
+async fn fire_event_autocomplete<'a>(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> serenity::all::CreateAutocompleteResponse<'a> {
+    let mut choices = serenity::all::CreateAutocompleteResponse::new();
+
+    for preset in MODERATION_PRESETS
+        .iter()
+        .filter(|p| p.contains(&partial.to_lowercase()))
+    {
+        choices = choices.add_choice(serenity::all::AutocompleteChoice::new(*preset, *preset));
+    }
+
+    choices
+}
+
+/// Reason prefix of synthetic moderation events
+const SYNTHETIC_REASON: &str = "Synthetic event from /templates fire_event";
+
+/// Correlation ID of synthetic moderation events
+///
+/// Real moderation actions always use a random (v4) ID, so templates can compare `correlation_id` against
+/// the nil UUID to tell a synthetic event apart and skip acting on it
+const SYNTHETIC_CORRELATION_ID: uuid::Uuid = uuid::Uuid::nil();
+
+/// Whether a dispatch error came from not reaching the template worker in time, rather than from a template
+fn is_worker_unavailable(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return e.is_connect() || e.is_timeout();
+        }
+
+        source = e.source();
+    }
+
+    false
+}
+
+/// Builds a `ModerationStart` event for a moderation preset, acting as the invoking member
+async fn build_moderation_event(
+    ctx: &Context<'_>,
+    preset: &str,
+    user: Option<serenity::all::User>,
+    reason: Option<String>,
+    duration: Option<String>,
+) -> Result<AntiraidEvent, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    let Some(user) = user else {
+        return Err(format!("`{}` needs a target `user`", preset).into());
+    };
+
+    let Some(author) = ctx.author_member().await else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    let duration_secs = match duration {
+        Some(duration) => {
+            let (duration, unit) = parse_duration_string(&duration)?;
+            Some(duration * unit.to_seconds())
+        }
+        None => None,
+    };
+
+    let action = match preset {
+        "moderation:kick" => ModerationAction::Kick {
+            member: ctx.http().get_member(guild_id, user.id).await?,
+        },
+        "moderation:ban" => ModerationAction::Ban { user, prune_dmd: 0 },
+        "moderation:tempban" => ModerationAction::TempBan {
+            user,
+            duration: duration_secs.ok_or("`moderation:tempban` needs a `duration`")?,
+            prune_dmd: 0,
+        },
+        "moderation:timeout" => ModerationAction::Timeout {
+            member: ctx.http().get_member(guild_id, user.id).await?,
+            duration: duration_secs.ok_or("`moderation:timeout` needs a `duration`")?,
+        },
+        "moderation:unban" => ModerationAction::Unban { user },
+        _ => return Err(format!("Unknown moderation preset `{}`", preset).into()),
+    };
+
+    // The event type has no dry-run field, so the correlation ID marks it as synthetic for templates to check
+    Ok(AntiraidEvent::ModerationStart(ModerationStartEventData {
+        correlation_id: SYNTHETIC_CORRELATION_ID,
+        reason: Some(match reason {
+            Some(reason) => format!("{}: {}", SYNTHETIC_REASON, reason),
+            None => SYNTHETIC_REASON.to_string(),
+        }),
+        action,
+        author: author.into_owned(),
+        num_stings: 0,
+    }))
+}
+
+/// Fires a synthetic event at the templates of this server and shows their results
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "fire_event")]
+pub async fn templates_fire_event(
+    ctx: Context<'_>,
+    #[autocomplete = "fire_event_autocomplete"]
+    #[description = "A moderation preset such as moderation:kick, or the name of any AntiRaid event"]
+    event_type: String,
+    #[description = "JSON payload of the event. Not needed for moderation presets"] payload: Option<
+        String,
+    >,
+    #[description = "Target user for moderation presets"] user: Option<serenity::all::User>,
+    #[description = "Reason for moderation presets"]
+    #[max_length = 384]
+    reason: Option<String>,
+    #[description = "Duration for the tempban and timeout presets, e.g. 1h"] duration: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.fire_event".into(),
+    )
+    .await?;
+
+    ctx.defer_ephemeral().await?;
+
+    let event = if MODERATION_PRESETS.contains(&event_type.as_str()) {
+        build_moderation_event(&ctx, &event_type, user, reason, duration).await?
+    } else {
+        let payload: serde_json::Value = match payload {
+            Some(payload) => serde_json::from_str(&payload)
+                .map_err(|e| format!("Payload must be valid JSON: {}", e))?,
+            None => serde_json::Value::Null,
+        };
+
+        // Events are externally tagged, i.e. {"EventName": payload}
+        let mut event = serde_json::Map::new();
+        event.insert(event_type.clone(), payload);
+
+        serde_json::from_value(serde_json::Value::Object(event))
+            .map_err(|e| format!("Invalid `{}` event: {}", event_type, e))?
+    };
+
+    // Templates handle synthetic events like real ones and can act on them, e.g. really kick the target member
+    let confirm_msg = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("Fire Event?")
+                        .description(format!(
+                            "Templates receive `{}` like a real event and may really act on it{}. Are you sure you want to fire it?",
+                            event_type.replace('`', "\\`"),
+                            if MODERATION_PRESETS.contains(&event_type.as_str()) {
+                                format!(
+                                    ", including against the target member, unless they skip events with the correlation ID `{}`",
+                                    SYNTHETIC_CORRELATION_ID
+                                )
+                            } else {
+                                String::new()
+                            }
+                        ))
+                        .color(serenity::all::Colour::ORANGE),
+                )
+                .components(vec![CreateActionRow::buttons(vec![
+                    CreateButton::new("fire_event_yes")
+                        .label("Fire")
+                        .style(serenity::all::ButtonStyle::Danger),
+                    CreateButton::new("fire_event_no")
+                        .label("Cancel")
+                        .style(serenity::all::ButtonStyle::Primary),
+                ])])
+                .ephemeral(true),
+        )
+        .await?
+        .into_message()
+        .await?;
+
+    let Some(item) = confirm_msg
+        .id
+        .await_component_interaction(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(60))
+        .await
+    else {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("You took too long to respond, the event was not fired")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    item.defer(&ctx.serenity_context().http).await?;
+
+    if item.data.custom_id.as_str() != "fire_event_yes" {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Cancelled, the event was not fired")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Use the same timeout as real moderation actions so results match what would really happen
+    let results = event
+        .dispatch_to_template_worker_and_wait(
+            ctx.data(),
+            guild_id,
+            &template_dispatch_data(),
+            Duration::from_secs(1),
+        )
+        .await;
+
+    let mut embed = CreateEmbed::default()
+        .title(format!("Fired {}", event_type))
+        .footer(serenity::all::CreateEmbedFooter::new(
+            "Templates receive this event like a real one and may still take actions unless they check for synthetic events",
+        ));
+
+    match results {
+        Err(e) if is_worker_unavailable(&*e) => {
+            embed = embed
+                .description(format!(
+                    ":warning: **Template worker unavailable**: no template result was received. A real moderation action would also have failed\n\n```\n{}\n```",
+                    truncate(&e.to_string(), 1024)
+                ))
+                .color(serenity::all::Colour::ORANGE);
+        }
+        Err(e) => {
+            embed = embed
+                .description(format!(
+                    ":no_entry: **Blocked**: a template errored, so a real moderation action would have been stopped\n\n```\n{}\n```",
+                    truncate(&e.to_string(), 1024)
+                ))
+                .color(serenity::all::Colour::RED);
+        }
+        Ok(results) => {
+            embed = embed
+                .description(format!(
+                    "{} templates responded\n**can_execute**: `{}` ({})",
+                    results.results.len(),
+                    results.can_execute(),
+                    if results.can_execute() {
+                        "templates allow the action"
+                    } else {
+                        "AntiRaid would fall back to its own hierarchy checks"
+                    }
+                ))
+                .color(serenity::all::Colour::DARK_GREEN);
+
+            // Discord allows at most 25 fields and 6000 characters per embed, the description and footer
+            // take up well under 1000 of them
+            let mut used = 0;
+            for (i, (name, result)) in results.results.iter().enumerate() {
+                let can_execute = match result.get("can_execute") {
+                    Some(serde_json::Value::Bool(b)) => b.to_string(),
+                    _ => "not set".to_string(),
+                };
+
+                let mut json = serde_json::to_string_pretty(result)?;
+                json = truncate(&json, 900);
+
+                let value = format!("**can_execute**: `{}`\n```json\n{}\n```", can_execute, json);
+
+                if i == 24 || used + name.len() + value.len() > 4800 {
+                    embed = embed.field(
+                        "More Results",
+                        format!(
+                            "...and {} more templates not shown",
+                            results.results.len() - i
+                        ),
+                        false,
+                    );
+                    break;
+                }
+
+                used += name.len() + value.len();
+                embed = embed.field(name.clone(), value, false);
+            }
+        }
+    }
+
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Exports a template on this server to a file which can be imported on another server
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "export")]
+pub async fn templates_export(
+    ctx: Context<'_>,
+    #[autocomplete = "installed_template_autocomplete"]
+    #[description = "The template to export"]
+    name: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.export".into(),
+    )
+    .await?;
+
+    #[derive(sqlx::FromRow)]
+    struct TemplateRecord {
+        content: serde_json::Value,
+        events: Vec<String>,
+        allowed_caps: Vec<String>,
+    }
+
+    let Some(rec): Option<TemplateRecord> = sqlx::query_as(
+        "SELECT content, events, allowed_caps FROM guild_templates WHERE guild_id = $1 AND name = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(&name)
+    .fetch_optional(&ctx.data().pool)
+    .await?
+    else {
+        return Err("No template with that name is loaded on this server".into());
+    };
+
+    if rec.content.is_null() {
+        return Err(
+            "Shop templates have no content of their own. Load them from the template shop instead"
+                .into(),
+        );
+    }
+
+    let export = TemplateExport {
+        format_version: EXPORT_FORMAT_VERSION,
+        name,
+        content: rec.content,
+        events: rec.events,
+        allowed_caps: rec.allowed_caps,
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "Exported `{}`. Use `/templates import` to load it on another server",
+                export.name.replace('`', "\\`")
+            ))
+            .attachment(serenity::all::CreateAttachment::bytes(
+                serde_json::to_vec_pretty(&export)?,
+                export.file_name(),
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Imports a template exported with /templates export
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "import")]
+pub async fn templates_import(
+    ctx: Context<'_>,
+    #[description = "The exported template file"] file: serenity::all::Attachment,
+    #[description = "Name to import the template as. Defaults to its exported name"] name: Option<
+        String,
+    >,
+    #[description = "Channel to send errors to"] error_channel: Option<serenity::all::GuildChannel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.import".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    if file.size as usize > MAX_EXPORT_SIZE {
+        return Err(format!(
+            "Template file is too large (max {} KB)",
+            MAX_EXPORT_SIZE / 1024
+        )
+        .into());
+    }
+
+    let contents = file
+        .download()
+        .await
+        .map_err(|e| format!("Failed to download template file: {}", e))?;
+
+    let mut export = TemplateExport::from_file(&contents)?;
+
+    if let Some(name) = name {
+        export.name = name;
+        export.validate()?;
+    }
+
+    #[derive(sqlx::FromRow)]
+    struct ExistingRecord {
+        events: Vec<String>,
+        allowed_caps: Vec<String>,
+    }
+
+    let existing: Option<ExistingRecord> = sqlx::query_as(
+        "SELECT events, allowed_caps FROM guild_templates WHERE guild_id = $1 AND name = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(&export.name)
+    .fetch_optional(&data.pool)
+    .await?;
+
+    let diff = CapabilityDiff::new(
+        existing
+            .as_ref()
+            .map(|e| (e.allowed_caps.as_slice(), e.events.as_slice())),
+        &export.allowed_caps,
+        &export.events,
+    );
+
+    let Some((confirm, allowed_caps)) = confirm_capabilities(
+        &ctx,
+        CreateEmbed::default()
+            .title("Import Template?")
+            .description(format!(
+                "Are you sure you want to import the custom template `{}`?{}",
+                export.name.replace('`', "\\`"),
+                if existing.is_some() {
+                    "\n\nA template with this name is already loaded and will be **replaced**. Changes are shown against it"
+                } else {
+                    ""
+                }
+            )),
+        &diff,
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let error_channel = error_channel
+        .map(|c| c.id)
+        .unwrap_or(ctx.channel_id())
+        .to_string();
+
+    if existing.is_some() {
+        sqlx::query(
+            "UPDATE guild_templates SET content = $3, events = $4, allowed_caps = $5, error_channel = $6, last_updated_by = $7 WHERE guild_id = $1 AND name = $2",
+        )
+        .bind(guild_id.to_string())
+        .bind(&export.name)
+        .bind(&export.content)
+        .bind(&export.events)
+        .bind(&allowed_caps)
+        .bind(&error_channel)
+        .bind(ctx.author().id.to_string())
+        .execute(&data.pool)
+        .await
+        .map_err(|e| format!("Failed to import template: {:?}", e))?;
+    } else {
+        sqlx::query(
+            "INSERT INTO guild_templates (guild_id, name, content, events, allowed_caps, error_channel, created_by, last_updated_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(guild_id.to_string())
+        .bind(&export.name)
+        .bind(&export.content)
+        .bind(&export.events)
+        .bind(&allowed_caps)
+        .bind(&error_channel)
+        .bind(ctx.author().id.to_string())
+        .bind(ctx.author().id.to_string())
+        .execute(&data.pool)
+        .await
+        .map_err(|e| format!("Failed to import template: {:?}", e))?;
+    }
+
+    dispatch_template_changes(ctx.data(), guild_id, vec![export.name.clone()], &[]).await?;
+
+    confirm
+        .create_response(
+            ctx.http(),
+            serenity::all::CreateInteractionResponse::Message(
+                serenity::all::CreateInteractionResponseMessage::new().content(format!(
+                    "Template `{}` imported successfully!",
+                    export.name.replace('`', "\\`")
+                )),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Shows the errors a template on this server has raised
+#[poise::command(slash_command, guild_only, user_cooldown = "5", rename = "errors")]
+pub async fn templates_errors(
+    ctx: Context<'_>,
+    #[autocomplete = "installed_template_autocomplete"]
+    #[description = "The template to show the errors of"]
+    name: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Err("This command can only be used in a guild".into());
+    };
+
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        ctx.author().id,
+        &ctx.data().pool,
+        ctx.serenity_context(),
+        &ctx.data().reqwest,
+        &Some(ctx),
+        "templates.errors".into(),
+    )
+    .await?;
+
+    let data = ctx.data();
+
+    let counts = templateerrors::counts(&data.pool, guild_id, &name).await?;
+
+    if counts.total == 0 {
+        ctx.say(format!(
+            "`{}` has not raised any errors",
+            name.replace('`', "\\`")
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    /// Number of errors shown per page
+    const PAGE_SIZE: i64 = 5;
+
+    let pages = (counts.total + PAGE_SIZE - 1) / PAGE_SIZE;
+
+    async fn create_reply<'a>(
+        pool: &sqlx::PgPool,
+        guild_id: GuildId,
+        name: &str,
+        counts: &templateerrors::ErrorCounts,
+        page: i64,
+        pages: i64,
+    ) -> Result<poise::CreateReply<'a>, Error> {
+        let errors =
+            templateerrors::list(pool, guild_id, name, PAGE_SIZE, page * PAGE_SIZE).await?;
+
+        let mut embed = CreateEmbed::default()
+            .title(format!("Errors of {}", name))
+            .description(format!(
+                "**Total**: {}\n**Last 24 hours**: {}\n**By event**: {}",
+                counts.total,
+                counts.last_day,
+                counts
+                    .by_event
+                    .iter()
+                    .take(5)
+                    .map(|(event, count)| format!("`{}` ({})", event, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .footer(serenity::all::CreateEmbedFooter::new(format!(
+                "Page {} of {}",
+                page + 1,
+                pages
+            )))
+            .color(serenity::all::Colour::RED);
+
+        for error in errors {
+            let mut text = error.error.clone();
+            text = truncate(&text, 900);
+
+            embed = embed.field(
+                format!(
+                    "{} | {}",
+                    error.event_type,
+                    error.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+                ),
+                format!("```\n{}\n```", text.replace("```", "`\u{200b}``")),
+                false,
+            );
+        }
+
+        Ok(poise::CreateReply::default()
+            .embed(embed)
+            .components(vec![CreateActionRow::buttons(vec![
+                CreateButton::new("errors_previous")
+                    .label("Previous")
+                    .style(serenity::all::ButtonStyle::Primary)
+                    .disabled(page == 0),
+                CreateButton::new("errors_next")
+                    .label("Next")
+                    .style(serenity::all::ButtonStyle::Primary)
+                    .disabled(page + 1 >= pages),
+            ])]))
+    }
+
+    let mut page = 0;
+
+    let msg = ctx
+        .send(create_reply(&data.pool, guild_id, &name, &counts, page, pages).await?)
+        .await?
+        .into_message()
+        .await?;
+
+    let mut collect_stream = msg
+        .id
+        .await_component_interactions(ctx.serenity_context().shard.clone())
+        .author_id(ctx.author().id)
+        .timeout(Duration::from_secs(180))
+        .stream();
+
+    while let Some(item) = collect_stream.next().await {
+        match item.data.custom_id.as_str() {
+            "errors_previous" => page = (page - 1).max(0),
+            "errors_next" => page = (page + 1).min(pages - 1),
+            _ => continue,
+        }
+
+        item.defer(&ctx.serenity_context().http).await?;
+
+        item.edit_response(
+            &ctx.serenity_context().http,
+            create_reply(&data.pool, guild_id, &name, &counts, page, pages)
+                .await?
+                .to_slash_initial_response_edit(serenity::all::EditInteractionResponse::default()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn startup_templates_names_changed_when_nothing_removed() {
+        assert_eq!(
+            startup_templates(names(&["a"]), &[], names(&["a", "b"])),
+            names(&["a"])
+        );
+    }
+
+    #[test]
+    fn startup_templates_names_remaining_when_removed() {
+        // Unloading `b` restarts everything else so the worker drops it
+        assert_eq!(
+            startup_templates(Vec::new(), &names(&["b"]), names(&["a", "c"])),
+            names(&["a", "c"])
+        );
+
+        // Upgrading renames `shop/x@1.0.0` to `shop/x@2.0.0`, the old name must never be sent
+        assert_eq!(
+            startup_templates(
+                names(&["shop/x@2.0.0"]),
+                &names(&["shop/x@1.0.0"]),
+                names(&["a", "shop/x@1.0.0", "shop/x@2.0.0"])
+            ),
+            names(&["a", "shop/x@2.0.0"])
+        );
+    }
+
+    #[test]
+    fn template_error_is_not_worker_unavailable() {
+        let e: Error = "Template `a` errored: blocked".into();
+        assert!(!is_worker_unavailable(&*e));
+    }
+
+    #[tokio::test]
+    async fn unreachable_worker_is_unavailable() {
+        // Nothing listens on the discard port
+        let e: Error = reqwest::Client::new()
+            .post("http://127.0.0.1:9/dispatch-event/0")
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+
+        assert!(is_worker_unavailable(&*e));
+    }
+
+    #[test]
+    fn startup_templates_empty_when_last_template_removed() {
+        assert!(startup_templates(Vec::new(), &names(&["a"]), Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,566 @@
+use super::backupfile::{self, BackupChannel, BackupGuild};
+use indexmap::IndexMap;
+use serenity::all::GuildId;
+use silverpelt::data::Data;
+use silverpelt::objectstore::guild_bucket;
+use sqlx::types::uuid::Uuid;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Sting and punishment records, as created by `silverpelt::stings` and `silverpelt::punishments`
+const MODERATION_TABLES: &[&str] = &["stings", "punishments"];
+
+/// Kittycat role permissions
+pub const ROLE_PERMISSIONS_TABLE: &str = "guild_roles";
+
+/// Templates loaded into the guild
+pub const TEMPLATES_TABLE: &str = "guild_templates";
+
+/// Active lockdowns, which are re-applied through a `LockdownSet` instead of being inserted as rows
+pub const LOCKDOWNS_TABLE: &str = "lockdown__guild_lockdowns";
+
+/// Tables which grant permissions or capabilities and so are only imported once the user has reviewed them
+const REVIEW_TABLES: &[&str] = &[ROLE_PERMISSIONS_TABLE, TEMPLATES_TABLE];
+
+/// All tables included in an export
+const EXPORT_TABLES: &[&str] = &[
+    "stings",
+    "punishments",
+    ROLE_PERMISSIONS_TABLE,
+    TEMPLATES_TABLE,
+    LOCKDOWNS_TABLE,
+];
+
+/// Exported configuration rows, keyed by table
+pub type ExportedConfig = IndexMap<String, Vec<serde_json::Value>>;
+
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq)]
+pub enum ConflictMode {
+    #[name = "skip"]
+    Skip,
+    #[name = "overwrite"]
+    Overwrite,
+}
+
+#[derive(Default)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: Vec<String>,
+    /// Names of the templates which were imported
+    pub templates: Vec<String>,
+}
+
+/// A lockdown of the backed up guild which should be re-applied
+#[derive(Debug, PartialEq)]
+pub struct PendingLockdown {
+    pub lockdown_type: String,
+    pub target: Option<String>,
+    pub reason: String,
+}
+
+impl PendingLockdown {
+    /// The lockdown's type and target as stored by `LockdownSet`, e.g. `scl/<channel_id>`
+    pub fn string_form(&self) -> String {
+        match self.target {
+            Some(ref target) => format!("{}/{}", self.lockdown_type, target),
+            None => self.lockdown_type.clone(),
+        }
+    }
+}
+
+/// Maps the role and channel IDs of a backup onto the IDs of the restored guild
+///
+/// Restored roles and channels are matched by name (and channel type), names which are not unique on both sides are left unmapped
+pub fn id_map(backup: &BackupGuild, live: &BackupGuild) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+
+    for role in &backup.roles {
+        let backup_matches = backup.roles.iter().filter(|r| r.name == role.name).count();
+        let live_matches = live
+            .roles
+            .iter()
+            .filter(|r| r.name == role.name)
+            .collect::<Vec<_>>();
+
+        if backup_matches == 1 && live_matches.len() == 1 {
+            map.insert(role.id.to_string(), live_matches[0].id.to_string());
+        }
+    }
+
+    for channel in &backup.channels {
+        let same = |c: &&BackupChannel| c.name == channel.name && c.kind == channel.kind;
+
+        let backup_matches = backup.channels.iter().filter(same).count();
+        let live_matches = live.channels.iter().filter(same).collect::<Vec<_>>();
+
+        if backup_matches == 1 && live_matches.len() == 1 {
+            map.insert(channel.id.to_string(), live_matches[0].id.to_string());
+        }
+    }
+
+    map
+}
+
+/// Replaces all role and channel IDs of a backup within an exported row
+fn remap_ids(value: &mut serde_json::Value, id_map: &HashMap<String, String>) {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some(new_id) = id_map.get(s.as_str()) {
+                *s = new_id.clone();
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                remap_ids(value, id_map);
+            }
+        }
+        serde_json::Value::Object(obj) => {
+            for value in obj.values_mut() {
+                remap_ids(value, id_map);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Prepares an exported row for import into a guild
+///
+/// Role and channel IDs are remapped and the row is moved to the guild. Rows from another server also get fresh
+/// UUID keys, as keeping the keys would collide with (or overwrite) the rows of the server they were exported from
+fn prepare_row(
+    mut row: serde_json::Value,
+    guild_id: GuildId,
+    id_map: &HashMap<String, String>,
+    pkey: &[String],
+) -> serde_json::Value {
+    remap_ids(&mut row, id_map);
+
+    let Some(obj) = row.as_object_mut() else {
+        return row;
+    };
+
+    let guild_id = guild_id.to_string();
+    let cross_guild = obj.get("guild_id").and_then(|g| g.as_str()) != Some(guild_id.as_str());
+
+    if cross_guild {
+        for column in pkey.iter().filter(|c| *c != "guild_id") {
+            if let Some(value) = obj.get_mut(column) {
+                if value.as_str().is_some_and(|v| v.parse::<Uuid>().is_ok()) {
+                    *value = serde_json::Value::String(Uuid::new_v4().to_string());
+                }
+            }
+        }
+    }
+
+    obj.insert("guild_id".to_string(), serde_json::Value::String(guild_id));
+
+    row
+}
+
+/// Returns the lockdowns of an export which should be re-applied, with their targets remapped
+pub fn pending_lockdowns(
+    config: &ExportedConfig,
+    id_map: &HashMap<String, String>,
+) -> Vec<PendingLockdown> {
+    let Some(rows) = config.get(LOCKDOWNS_TABLE) else {
+        return Vec::new();
+    };
+
+    rows.iter()
+        .filter_map(|row| {
+            let string_form = row.get("type")?.as_str()?;
+            let reason = row
+                .get("reason")
+                .and_then(|r| r.as_str())
+                .unwrap_or_default();
+
+            let (lockdown_type, target) = match string_form.split_once('/') {
+                Some((lockdown_type, target)) => (
+                    lockdown_type,
+                    Some(id_map.get(target).map(|t| t.as_str()).unwrap_or(target)),
+                ),
+                None => (string_form, None),
+            };
+
+            Some(PendingLockdown {
+                lockdown_type: lockdown_type.to_string(),
+                target: target.map(|t| t.to_string()),
+                reason: format!("Restored from backup: {}", reason),
+            })
+        })
+        .collect()
+}
+
+/// Returns the names of the templates of an export along with the capabilities they were allowed
+pub fn templates(config: &ExportedConfig) -> Vec<(String, Vec<String>)> {
+    let Some(rows) = config.get(TEMPLATES_TABLE) else {
+        return Vec::new();
+    };
+
+    rows.iter()
+        .filter_map(|row| {
+            let name = row.get("name")?.as_str()?.to_string();
+            let caps = row
+                .get("allowed_caps")
+                .and_then(|c| c.as_array())
+                .map(|c| {
+                    c.iter()
+                        .filter_map(|c| c.as_str().map(|c| c.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            Some((name, caps))
+        })
+        .collect()
+}
+
+/// Returns the number of kittycat role permission entries of an export
+pub fn role_permissions(config: &ExportedConfig) -> usize {
+    config
+        .get(ROLE_PERMISSIONS_TABLE)
+        .map(|rows| rows.len())
+        .unwrap_or(0)
+}
+
+/// Returns the primary key columns of a table
+async fn primary_key(pool: &PgPool, table: &str) -> Result<Vec<String>, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct ColumnRecord {
+        attname: String,
+    }
+
+    let columns: Vec<ColumnRecord> = sqlx::query_as(
+        "SELECT a.attname::text FROM pg_index i JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) WHERE i.indrelid = $1::regclass AND i.indisprimary",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+
+    if columns.is_empty() {
+        return Err(format!("Table {} has no primary key", table).into());
+    }
+
+    Ok(columns.into_iter().map(|c| c.attname).collect())
+}
+
+/// Exports all AntiRaid configuration of a guild
+pub async fn export(pool: &PgPool, guild_id: GuildId) -> Result<ExportedConfig, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct RowRecord {
+        row: serde_json::Value,
+    }
+
+    let mut config = ExportedConfig::new();
+
+    for table in EXPORT_TABLES {
+        let rows: Vec<RowRecord> = sqlx::query_as(&format!(
+            "SELECT row_to_json(t) AS row FROM {} t WHERE guild_id = $1",
+            table
+        ))
+        .bind(guild_id.to_string())
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to export {}: {}", table, e))?;
+
+        config.insert(table.to_string(), rows.into_iter().map(|r| r.row).collect());
+    }
+
+    Ok(config)
+}
+
+/// Imports previously exported AntiRaid configuration into a guild once its backup has been restored
+///
+/// Rows whose primary key already exists are either skipped or replaced depending on `mode`. Role permissions
+/// and templates are only imported if `reviewed` is set, lockdowns are never imported as rows (see `pending_lockdowns`)
+pub async fn import(
+    pool: &PgPool,
+    guild_id: GuildId,
+    config: &ExportedConfig,
+    mode: ConflictMode,
+    id_map: &HashMap<String, String>,
+    reviewed: bool,
+) -> Result<ImportResult, crate::Error> {
+    let mut result = ImportResult::default();
+
+    for (table, rows) in config {
+        let is_review_table = REVIEW_TABLES.contains(&table.as_str());
+
+        // Table names come from the (untrusted) export, so only known tables are ever written to
+        if !MODERATION_TABLES.contains(&table.as_str()) && !is_review_table {
+            if table != LOCKDOWNS_TABLE {
+                result
+                    .failed
+                    .push(format!("{}: not an AntiRaid configuration table", table));
+            }
+            continue;
+        }
+
+        if is_review_table && !reviewed {
+            result.skipped += rows.len();
+            continue;
+        }
+
+        let pkey = primary_key(pool, table).await?;
+        let pkey_list = pkey.join(", ");
+
+        for row in rows {
+            let row = prepare_row(row.clone(), guild_id, id_map, &pkey);
+
+            let mut tx = pool.begin().await?;
+
+            if mode == ConflictMode::Overwrite {
+                sqlx::query(&format!(
+                    "DELETE FROM {table} WHERE guild_id = $2 AND ({pkey_list}) = (SELECT {pkey_list} FROM json_populate_record(NULL::{table}, $1))",
+                ))
+                .bind(&row)
+                .bind(guild_id.to_string())
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            let res = sqlx::query(&format!(
+                "INSERT INTO {table} SELECT * FROM json_populate_record(NULL::{table}, $1) ON CONFLICT DO NOTHING",
+            ))
+            .bind(&row)
+            .execute(&mut *tx)
+            .await;
+
+            match res {
+                Ok(res) if res.rows_affected() == 0 => result.skipped += 1,
+                Ok(_) => {
+                    result.imported += 1;
+
+                    if table == TEMPLATES_TABLE {
+                        if let Some(name) = row.get("name").and_then(|n| n.as_str()) {
+                            result.templates.push(name.to_string());
+                        }
+                    }
+                }
+                Err(e) => {
+                    result.failed.push(format!("{}: {}", table, e));
+                    continue;
+                }
+            }
+
+            tx.commit().await?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns where the exported configuration of a backup is stored in the guild's bucket
+fn export_path(job_id: Uuid) -> String {
+    format!("antiraid_config/{}.json", job_id)
+}
+
+/// Stores the exported configuration of a backup next to it, encrypted with the backup's password if it has one
+///
+/// The backup file itself is written by the jobserver, which knows nothing of AntiRaid's configuration
+pub async fn save(
+    data: &Data,
+    guild_id: GuildId,
+    job_id: Uuid,
+    config: &ExportedConfig,
+    password: Option<&str>,
+) -> Result<(), crate::Error> {
+    let contents = serde_json::to_vec(config)?;
+
+    let contents = match password {
+        Some(password) if !password.is_empty() => backupfile::encrypt(&contents, password)?,
+        _ => contents,
+    };
+
+    data.object_store
+        .upload_file(&guild_bucket(guild_id), &export_path(job_id), contents)
+        .await
+        .map_err(|e| format!("Failed to store AntiRaid configuration: {}", e))?;
+
+    Ok(())
+}
+
+/// Loads the exported configuration stored next to a backup of `guild_id`
+pub async fn load(
+    data: &Data,
+    guild_id: GuildId,
+    job_id: Uuid,
+    password: Option<&str>,
+) -> Result<ExportedConfig, crate::Error> {
+    let url = data
+        .object_store
+        .get_url(
+            &guild_bucket(guild_id),
+            &export_path(job_id),
+            std::time::Duration::from_secs(60),
+        )
+        .await
+        .map_err(|e| format!("Failed to get AntiRaid configuration url: {}", e))?;
+
+    let contents = data
+        .reqwest
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|e| format!("Failed to download AntiRaid configuration: {}", e))?
+        .bytes()
+        .await?
+        .to_vec();
+
+    let contents = match password {
+        Some(password) if !password.is_empty() => backupfile::decrypt(&contents, password)?,
+        _ => contents,
+    };
+
+    serde_json::from_slice(&contents)
+        .map_err(|e| format!("Failed to parse AntiRaid configuration: {}", e).into())
+}
+
+/// Deletes the exported configuration stored next to a backup
+pub async fn delete(data: &Data, guild_id: GuildId, job_id: Uuid) -> Result<(), crate::Error> {
+    data.object_store
+        .delete(&guild_bucket(guild_id), &export_path(job_id))
+        .await
+        .map_err(|e| format!("Failed to delete AntiRaid configuration: {}", e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::botlib::backupfile::BackupRole;
+    use serenity::all::{ChannelId, RoleId};
+
+    fn role(id: u64, name: &str) -> BackupRole {
+        BackupRole {
+            id: RoleId::new(id),
+            name: name.to_string(),
+            permissions: 0,
+            color: 0,
+            position: 1,
+            managed: false,
+        }
+    }
+
+    fn channel(id: u64, name: &str, kind: u8) -> BackupChannel {
+        BackupChannel {
+            id: ChannelId::new(id),
+            name: name.to_string(),
+            kind,
+            position: 0,
+            parent_id: None,
+            permission_overwrites: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_id_map() {
+        let backup = BackupGuild {
+            roles: vec![
+                role(1, "mod"),
+                role(2, "dup"),
+                role(3, "dup"),
+                role(4, "gone"),
+            ],
+            channels: vec![channel(10, "general", 0), channel(11, "general", 2)],
+        };
+        let live = BackupGuild {
+            roles: vec![role(101, "mod"), role(102, "dup")],
+            channels: vec![channel(110, "general", 0), channel(111, "general", 2)],
+        };
+
+        let map = id_map(&backup, &live);
+        assert_eq!(map.get("1").map(|s| s.as_str()), Some("101"));
+        // Names which are not unique in the backup cannot be matched
+        assert!(!map.contains_key("2"));
+        assert!(!map.contains_key("3"));
+        assert!(!map.contains_key("4"));
+        // Channels with the same name are told apart by type
+        assert_eq!(map.get("10").map(|s| s.as_str()), Some("110"));
+        assert_eq!(map.get("11").map(|s| s.as_str()), Some("111"));
+    }
+
+    #[test]
+    fn test_remap_ids() {
+        let map = HashMap::from([("1".to_string(), "101".to_string())]);
+        let mut value = serde_json::json!({
+            "role_id": "1",
+            "roles": ["1", "2"],
+            "nested": {"channel": "1"},
+            "count": 1,
+        });
+
+        remap_ids(&mut value, &map);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "role_id": "101",
+                "roles": ["101", "2"],
+                "nested": {"channel": "101"},
+                "count": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_prepare_row() {
+        let map = HashMap::from([("1".to_string(), "101".to_string())]);
+        let pkey = vec!["id".to_string()];
+        let id = Uuid::new_v4().to_string();
+        let row = serde_json::json!({"id": id, "guild_id": "5", "role_id": "1"});
+
+        // Same server: the key is kept
+        let same = prepare_row(row.clone(), GuildId::new(5), &map, &pkey);
+        assert_eq!(same["id"], id);
+        assert_eq!(same["role_id"], "101");
+
+        // Another server: a fresh key and the new guild
+        let other = prepare_row(row, GuildId::new(6), &map, &pkey);
+        assert_ne!(other["id"], id);
+        assert!(other["id"].as_str().unwrap().parse::<Uuid>().is_ok());
+        assert_eq!(other["guild_id"], "6");
+
+        // Keys which are not UUIDs (e.g. a template name) are kept
+        let template = serde_json::json!({"name": "x", "guild_id": "5"});
+        let other = prepare_row(
+            template,
+            GuildId::new(6),
+            &map,
+            &["guild_id".to_string(), "name".to_string()],
+        );
+        assert_eq!(other["name"], "x");
+    }
+
+    #[test]
+    fn test_pending_lockdowns() {
+        let map = HashMap::from([("10".to_string(), "110".to_string())]);
+        let mut config = ExportedConfig::new();
+        config.insert(
+            LOCKDOWNS_TABLE.to_string(),
+            vec![
+                serde_json::json!({"type": "tsl", "reason": "raid"}),
+                serde_json::json!({"type": "scl/10", "reason": "spam"}),
+                serde_json::json!({"reason": "no type"}),
+            ],
+        );
+
+        assert_eq!(
+            pending_lockdowns(&config, &map),
+            vec![
+                PendingLockdown {
+                    lockdown_type: "tsl".to_string(),
+                    target: None,
+                    reason: "Restored from backup: raid".to_string(),
+                },
+                PendingLockdown {
+                    lockdown_type: "scl".to_string(),
+                    target: Some("110".to_string()),
+                    reason: "Restored from backup: spam".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use super::backupfile::{BackupChannel, BackupGuild, BackupOverwrite};
+use serenity::all::Permissions;
+use std::fmt::Write;
+
+/// The differences between two versions of a guild
+#[derive(Default)]
+pub struct BackupDiff {
+    pub roles_added: Vec<String>,
+    pub roles_removed: Vec<String>,
+    pub roles_renamed: Vec<String>,
+    pub role_permissions_changed: Vec<String>,
+    pub channels_added: Vec<String>,
+    pub channels_removed: Vec<String>,
+    pub channels_moved: Vec<String>,
+    pub overwrites_changed: Vec<String>,
+}
+
+fn format_permissions(bits: u64) -> String {
+    let perms = Permissions::from_bits_truncate(bits);
+
+    if perms.is_empty() {
+        "none".to_string()
+    } else {
+        perms.to_string()
+    }
+}
+
+fn overwrite_target(ow: &BackupOverwrite, from: &BackupGuild, to: &BackupGuild) -> String {
+    if ow.kind == 1 {
+        return format!("member {}", ow.id);
+    }
+
+    let name = to
+        .roles
+        .iter()
+        .chain(from.roles.iter())
+        .find(|r| r.id.to_string() == ow.id)
+        .map(|r| r.name.as_str())
+        .unwrap_or("unknown");
+
+    format!("role @{} ({})", name, ow.id)
+}
+
+fn channel_location(channel: &BackupChannel, guild: &BackupGuild) -> String {
+    let parent = channel.parent_id.map(|parent_id| {
+        guild
+            .channels
+            .iter()
+            .find(|c| c.id == parent_id)
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| parent_id.to_string())
+    });
+
+    match parent {
+        Some(parent) => format!("in {} at position {}", parent, channel.position),
+        None => format!("at position {}", channel.position),
+    }
+}
+
+impl BackupDiff {
+    /// Computes the changes needed to go from `from` to `to`
+    pub fn new(from: &BackupGuild, to: &BackupGuild) -> Self {
+        let mut diff = Self::default();
+
+        for role in &to.roles {
+            match from.roles.iter().find(|r| r.id == role.id) {
+                Some(old) => {
+                    if old.name != role.name {
+                        diff.roles_renamed
+                            .push(format!("@{} -> @{} ({})", old.name, role.name, role.id));
+                    }
+
+                    if old.permissions != role.permissions {
+                        diff.role_permissions_changed.push(format!(
+                            "@{} ({}): {} -> {}",
+                            role.name,
+                            role.id,
+                            format_permissions(old.permissions),
+                            format_permissions(role.permissions)
+                        ));
+                    }
+                }
+                None => diff
+                    .roles_added
+                    .push(format!("@{} ({})", role.name, role.id)),
+            }
+        }
+
+        for role in &from.roles {
+            if !to.roles.iter().any(|r| r.id == role.id) {
+                diff.roles_removed
+                    .push(format!("@{} ({})", role.name, role.id));
+            }
+        }
+
+        for channel in &to.channels {
+            let Some(old) = from.channels.iter().find(|c| c.id == channel.id) else {
+                diff.channels_added
+                    .push(format!("#{} ({})", channel.name, channel.id));
+                continue;
+            };
+
+            if old.parent_id != channel.parent_id || old.position != channel.position {
+                diff.channels_moved.push(format!(
+                    "#{} ({}): {} -> {}",
+                    channel.name,
+                    channel.id,
+                    channel_location(old, from),
+                    channel_location(channel, to)
+                ));
+            }
+
+            for ow in &channel.permission_overwrites {
+                match old
+                    .permission_overwrites
+                    .iter()
+                    .find(|o| o.id == ow.id && o.kind == ow.kind)
+                {
+                    Some(old_ow) => {
+                        if old_ow.allow != ow.allow || old_ow.deny != ow.deny {
+                            diff.overwrites_changed.push(format!(
+                                "#{} {}: allow {} / deny {} -> allow {} / deny {}",
+                                channel.name,
+                                overwrite_target(ow, from, to),
+                                format_permissions(old_ow.allow),
+                                format_permissions(old_ow.deny),
+                                format_permissions(ow.allow),
+                                format_permissions(ow.deny)
+                            ));
+                        }
+                    }
+                    None => diff.overwrites_changed.push(format!(
+                        "#{} {}: added (allow {} / deny {})",
+                        channel.name,
+                        overwrite_target(ow, from, to),
+                        format_permissions(ow.allow),
+                        format_permissions(ow.deny)
+                    )),
+                }
+            }
+
+            for old_ow in &old.permission_overwrites {
+                if !channel
+                    .permission_overwrites
+                    .iter()
+                    .any(|o| o.id == old_ow.id && o.kind == old_ow.kind)
+                {
+                    diff.overwrites_changed.push(format!(
+                        "#{} {}: removed",
+                        channel.name,
+                        overwrite_target(old_ow, from, to)
+                    ));
+                }
+            }
+        }
+
+        for channel in &from.channels {
+            if !to.channels.iter().any(|c| c.id == channel.id) {
+                diff.channels_removed
+                    .push(format!("#{} ({})", channel.name, channel.id));
+            }
+        }
+
+        diff
+    }
+
+    fn sections(&self) -> [(&'static str, &Vec<String>); 8] {
+        [
+            ("Roles Added", &self.roles_added),
+            ("Roles Removed", &self.roles_removed),
+            ("Roles Renamed", &self.roles_renamed),
+            ("Role Permission Changes", &self.role_permissions_changed),
+            ("Channels Added", &self.channels_added),
+            ("Channels Removed", &self.channels_removed),
+            ("Channels Moved", &self.channels_moved),
+            ("Overwrite Changes", &self.overwrites_changed),
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections().iter().all(|(_, v)| v.is_empty())
+    }
+
+    /// Returns the number of changes in each non-empty section
+    pub fn summary(&self) -> Vec<(&'static str, usize)> {
+        self.sections()
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(name, v)| (*name, v.len()))
+            .collect()
+    }
+
+    /// Renders the full diff as plain text
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (name, changes) in self.sections() {
+            if changes.is_empty() {
+                continue;
+            }
+
+            let _ = writeln!(text, "== {} ({}) ==", name, changes.len());
+            for change in changes {
+                let _ = writeln!(text, "{}", change);
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+}
@@ -0,0 +1,528 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use argon2::Argon2;
+use serde::{Deserialize, Deserializer};
+use serenity::all::{ChannelId, GuildId, Http, PermissionOverwriteType, RoleId};
+use sha2::{Digest, Sha256};
+use silverpelt::data::Data;
+use silverpelt::objectstore::guild_bucket;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// The size of the random salt prefixed to encrypted backups
+const SALT_SIZE: usize = 16;
+
+/// The size of the AES-GCM nonce following the salt of encrypted backups
+const NONCE_SIZE: usize = 12;
+
+/// The section of a backup file containing the guild itself
+const GUILD_SECTION: &str = "core/guild";
+
+/// The largest backup that will be downloaded, as backups are read into memory in full
+pub const MAX_BACKUP_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Deserializes a permission bitset which may be sent either as a string or a number
+fn de_permissions<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StrOrInt {
+        Str(String),
+        Int(u64),
+    }
+
+    match StrOrInt::deserialize(deserializer)? {
+        StrOrInt::Str(s) => s.parse().map_err(serde::de::Error::custom),
+        StrOrInt::Int(i) => Ok(i),
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupRole {
+    pub id: RoleId,
+    pub name: String,
+    #[serde(deserialize_with = "de_permissions")]
+    pub permissions: u64,
+    #[serde(default)]
+    pub color: u32,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub managed: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupOverwrite {
+    /// The role or member ID the overwrite applies to
+    pub id: String,
+    /// 0 for roles, 1 for members
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(deserialize_with = "de_permissions")]
+    pub allow: u64,
+    #[serde(deserialize_with = "de_permissions")]
+    pub deny: u64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupChannel {
+    pub id: ChannelId,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub parent_id: Option<ChannelId>,
+    #[serde(default)]
+    pub permission_overwrites: Vec<BackupOverwrite>,
+}
+
+/// The roles and channels of a guild, either read from a backup or fetched from Discord
+#[derive(Deserialize, Clone, Default)]
+pub struct BackupGuild {
+    #[serde(default)]
+    pub roles: Vec<BackupRole>,
+    #[serde(default)]
+    pub channels: Vec<BackupChannel>,
+}
+
+impl BackupGuild {
+    /// Fetches the current roles and channels of a guild
+    pub async fn live(http: &Http, guild_id: GuildId) -> Result<Self, crate::Error> {
+        let roles = http.get_guild_roles(guild_id).await?;
+        let channels = http.get_channels(guild_id).await?;
+
+        Ok(Self {
+            roles: roles
+                .into_iter()
+                .map(|r| BackupRole {
+                    id: r.id,
+                    name: r.name.to_string(),
+                    permissions: r.permissions.bits(),
+                    color: r.colour.0,
+                    position: r.position as i64,
+                    managed: r.managed(),
+                })
+                .collect(),
+            channels: channels
+                .into_iter()
+                .map(|c| BackupChannel {
+                    id: c.id,
+                    name: c.name.to_string(),
+                    kind: u8::from(c.kind),
+                    position: c.position as i64,
+                    parent_id: c.parent_id,
+                    permission_overwrites: c
+                        .permission_overwrites
+                        .iter()
+                        .filter_map(|ow| {
+                            let (id, kind) = match ow.kind {
+                                PermissionOverwriteType::Role(role_id) => (role_id.to_string(), 0),
+                                PermissionOverwriteType::Member(user_id) => {
+                                    (user_id.to_string(), 1)
+                                }
+                                _ => return None,
+                            };
+
+                            Some(BackupOverwrite {
+                                id,
+                                kind,
+                                allow: ow.allow.bits(),
+                                deny: ow.deny.bits(),
+                            })
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Reads the guild out of the raw (possibly encrypted) contents of a backup file
+    pub fn from_backup(contents: Vec<u8>, password: Option<&str>) -> Result<Self, crate::Error> {
+        Self::from_sections(&read_sections(contents, password)?)
+    }
+
+    /// Reads the guild out of the sections of a backup file
+    pub fn from_sections(sections: &HashMap<String, Vec<u8>>) -> Result<Self, crate::Error> {
+        let Some(guild) = sections.get(GUILD_SECTION) else {
+            return Err("Backup does not contain a guild".into());
+        };
+
+        serde_json::from_slice(guild).map_err(|e| format!("Failed to parse backup: {}", e).into())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupMessageAuthor {
+    pub id: String,
+    pub username: String,
+    #[serde(default)]
+    pub avatar: Option<String>,
+}
+
+impl BackupMessageAuthor {
+    /// Returns a username Discord accepts for a webhook message
+    ///
+    /// Webhook usernames may not contain `discord` or `clyde`, may not be `everyone` or `here` and must be
+    /// 1-80 characters long
+    pub fn webhook_username(&self) -> String {
+        let mut username = self.username.trim().to_string();
+
+        for (word, replacement) in [("discord", "d_scord"), ("clyde", "cl_de")] {
+            // ASCII lowercasing keeps byte offsets the same
+            while let Some(pos) = username.to_ascii_lowercase().find(word) {
+                username.replace_range(pos..pos + word.len(), replacement);
+            }
+        }
+
+        let username: String = username.chars().take(80).collect();
+
+        if username.is_empty()
+            || username.eq_ignore_ascii_case("everyone")
+            || username.eq_ignore_ascii_case("here")
+        {
+            return "Unknown User".to_string();
+        }
+
+        username
+    }
+
+    pub fn avatar_url(&self) -> Option<String> {
+        self.avatar.as_ref().map(|avatar| {
+            format!(
+                "https://cdn.discordapp.com/avatars/{}/{}.png",
+                self.id, avatar
+            )
+        })
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BackupMessage {
+    #[serde(default)]
+    pub content: String,
+    pub author: BackupMessageAuthor,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub attachments: Vec<serde_json::Value>,
+}
+
+/// Returns the backed up messages of a channel, oldest first
+pub fn messages(
+    sections: &HashMap<String, Vec<u8>>,
+    channel_id: ChannelId,
+) -> Result<Vec<BackupMessage>, crate::Error> {
+    let Some(section) = sections.get(&format!("messages/{}", channel_id)) else {
+        return Err(format!(
+            "Backup does not contain messages for channel {}",
+            channel_id
+        )
+        .into());
+    };
+
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(section)
+        .map_err(|e| format!("Failed to parse backed up messages: {}", e))?;
+
+    let mut messages = Vec::new();
+    for mut entry in entries {
+        // Messages may be wrapped alongside their attachment metadata
+        let entry = match entry.get_mut("message") {
+            Some(message) => message.take(),
+            None => entry,
+        };
+
+        messages.push(serde_json::from_value::<BackupMessage>(entry)?);
+    }
+
+    messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    Ok(messages)
+}
+
+/// Decrypts a backup encrypted with a password
+///
+/// Encrypted backups are laid out as a random salt, then the nonce, then the AES-256-GCM ciphertext. The key is
+/// derived from the password and salt with Argon2id (default parameters).
+///
+/// The bot only reads backups, they are written by the `guild_create_backup` task of the jobserver (spawned through
+/// `jobserver::spawn`) when the `Encrypt` option is set. The layout here must match that task,
+/// `tests::test_round_trip_encrypted` pins the layout assumed by the bot
+pub fn decrypt(contents: &[u8], password: &str) -> Result<Vec<u8>, crate::Error> {
+    if contents.len() < SALT_SIZE + NONCE_SIZE {
+        return Err("Backup is too small to be encrypted".into());
+    }
+
+    let (salt, rest) = contents.split_at(SALT_SIZE);
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive backup key: {}", e))?;
+
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| format!("Invalid key: {}", e))?;
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt backup. Is the password correct?".into())
+}
+
+/// Encrypts data the same way as the jobserver encrypts backups, see `decrypt`
+///
+/// Used for data AntiRaid stores next to an encrypted backup
+pub fn encrypt(contents: &[u8], password: &str) -> Result<Vec<u8>, crate::Error> {
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("Failed to derive backup key: {}", e))?;
+
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|e| format!("Invalid key: {}", e))?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, contents)
+        .map_err(|e| format!("Failed to encrypt: {}", e))?;
+
+    Ok([salt.as_slice(), nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Reads all sections of a backup file into memory
+///
+/// Once decrypted, a backup is a tar archive with one entry per section as written by the jobserver's
+/// `guild_create_backup` task: the guild itself as JSON in `core/guild` and the messages of each backed up channel
+/// as JSON in `messages/<channel_id>`
+pub fn read_sections(
+    contents: Vec<u8>,
+    password: Option<&str>,
+) -> Result<HashMap<String, Vec<u8>>, crate::Error> {
+    let contents = match password {
+        Some(password) if !password.is_empty() => decrypt(&contents, password)?,
+        _ => contents,
+    };
+
+    let mut archive = tar::Archive::new(std::io::Cursor::new(contents));
+    let mut sections = HashMap::new();
+
+    for entry in archive
+        .entries()
+        .map_err(|e| format!("Failed to read backup. Is it encrypted? {}", e))?
+    {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+
+        sections.insert(name, buf);
+    }
+
+    Ok(sections)
+}
+
+/// Downloads the output of a backup job from the object store
+pub async fn download(data: &Data, job: &jobserver::Job) -> Result<Vec<u8>, crate::Error> {
+    let Some(path) = job.get_file_path() else {
+        return Err("Failed to find backup storage path".into());
+    };
+
+    let guild_id = job.guild_id;
+
+    let url = data
+        .object_store
+        .get_url(
+            &guild_bucket(guild_id),
+            &path,
+            std::time::Duration::from_secs(600),
+        )
+        .await
+        .map_err(|e| format!("Failed to get backup download url: {}", e))?;
+
+    let mut response = data.reqwest.get(&url).send().await?.error_for_status()?;
+
+    if response.content_length().unwrap_or(0) > MAX_BACKUP_SIZE {
+        return Err(too_large().into());
+    }
+
+    // Content-Length is not always sent, so the limit is enforced while reading as well
+    let mut contents = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (contents.len() + chunk.len()) as u64 > MAX_BACKUP_SIZE {
+            return Err(too_large().into());
+        }
+
+        contents.extend_from_slice(&chunk);
+    }
+
+    Ok(contents)
+}
+
+/// The error returned for a backup over `MAX_BACKUP_SIZE`
+pub fn too_large() -> String {
+    format!(
+        "Backup is larger than the maximum of {}",
+        crate::botlib::backupusage::format_bytes(MAX_BACKUP_SIZE)
+    )
+}
+
+/// Returns the lowercase hex encoded SHA-256 of a backup file
+pub fn checksum(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Stores the checksum and size of a backup
+pub async fn save_checksum(
+    pool: &sqlx::PgPool,
+    job: &jobserver::Job,
+    checksum: &str,
+    size: usize,
+) -> Result<(), crate::Error> {
+    sqlx::query(
+        "INSERT INTO backups__job_metadata (job_id, guild_id, checksum, size_bytes) VALUES ($1, $2, $3, $4) ON CONFLICT (job_id) DO UPDATE SET checksum = EXCLUDED.checksum, size_bytes = EXCLUDED.size_bytes",
+    )
+    .bind(job.id)
+    .bind(job.guild_id.to_string())
+    .bind(checksum)
+    .bind(size as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes the metadata of a backup along with the AntiRaid configuration stored next to it
+pub async fn delete_metadata(
+    data: &Data,
+    guild_id: GuildId,
+    job_id: sqlx::types::uuid::Uuid,
+) -> Result<(), crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct MetadataRecord {
+        has_antiraid_config: bool,
+    }
+
+    let rec: Option<MetadataRecord> =
+        sqlx::query_as("SELECT has_antiraid_config FROM backups__job_metadata WHERE job_id = $1")
+            .bind(job_id)
+            .fetch_optional(&data.pool)
+            .await?;
+
+    if rec.is_some_and(|r| r.has_antiraid_config) {
+        super::antiraidconfig::delete(data, guild_id, job_id).await?;
+    }
+
+    sqlx::query("DELETE FROM backups__job_metadata WHERE job_id = $1")
+        .bind(job_id)
+        .execute(&data.pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Downloads a completed backup and records its checksum and size
+pub async fn record_checksum(data: &Data, job: &jobserver::Job) -> Result<(), crate::Error> {
+    let contents = download(data, job).await?;
+    save_checksum(&data.pool, job, &checksum(&contents), contents.len()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: &str = r#"{
+        "roles": [{"id": "1", "name": "@everyone", "permissions": "1024"}],
+        "channels": [{"id": "2", "name": "general", "type": 0, "permission_overwrites": [{"id": "1", "type": 0, "allow": 0, "deny": "2048"}]}]
+    }"#;
+
+    const MESSAGES: &str = r#"[
+        {"message": {"content": "second", "author": {"id": "3", "username": "b"}, "timestamp": "2024-01-02T00:00:00Z"}},
+        {"content": "first", "author": {"id": "4", "username": "a", "avatar": "abc"}, "timestamp": "2024-01-01T00:00:00Z"}
+    ]"#;
+
+    fn archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for (name, contents) in [(GUILD_SECTION, GUILD), ("messages/2", MESSAGES)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn check_sections(sections: &HashMap<String, Vec<u8>>) {
+        let guild = BackupGuild::from_sections(sections).unwrap();
+        assert_eq!(guild.roles[0].permissions, 1024);
+        assert_eq!(guild.channels[0].permission_overwrites[0].deny, 2048);
+
+        let messages = messages(sections, ChannelId::new(2)).unwrap();
+        assert_eq!(messages[0].content, "first");
+        assert_eq!(
+            messages[0].author.avatar_url().unwrap(),
+            "https://cdn.discordapp.com/avatars/4/abc.png"
+        );
+        assert_eq!(messages[1].content, "second");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        check_sections(&read_sections(archive(), None).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip_encrypted() {
+        let encrypted = encrypt(&archive(), "hunter2").unwrap();
+
+        check_sections(&read_sections(encrypted.clone(), Some("hunter2")).unwrap());
+        assert!(read_sections(encrypted, Some("wrong")).is_err());
+    }
+
+    #[test]
+    fn test_missing_sections() {
+        let sections = HashMap::new();
+        assert!(BackupGuild::from_sections(&sections).is_err());
+        assert!(messages(&sections, ChannelId::new(2)).is_err());
+    }
+
+    #[test]
+    fn test_webhook_username() {
+        let author = |username: &str| BackupMessageAuthor {
+            id: "1".to_string(),
+            username: username.to_string(),
+            avatar: None,
+        };
+
+        assert_eq!(author("alice").webhook_username(), "alice");
+        assert_eq!(author("DiscordMod").webhook_username(), "d_scordMod");
+        assert_eq!(
+            author("my discord discord").webhook_username(),
+            "my d_scord d_scord"
+        );
+        assert_eq!(author("Clyde").webhook_username(), "cl_de");
+        assert_eq!(author("here").webhook_username(), "Unknown User");
+        assert_eq!(author("  ").webhook_username(), "Unknown User");
+        assert_eq!(author(&"a".repeat(100)).webhook_username().len(), 80);
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(
+            checksum(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::CONFIG;
+use serenity::all::GuildId;
+use silverpelt::data::Data;
+use silverpelt::objectstore::guild_bucket;
+use sqlx::types::uuid::Uuid;
+use std::collections::HashMap;
+
+/// The backup storage used by a guild
+pub struct GuildUsage {
+    /// Total size of all backups in bytes
+    pub used: u64,
+    /// Storage quota in bytes
+    pub quota: u64,
+    /// Size of each backup, largest first
+    pub backups: Vec<(Uuid, u64)>,
+}
+
+impl GuildUsage {
+    pub fn is_over_quota(&self) -> bool {
+        self.used >= self.quota
+    }
+
+    /// A short `used of quota (percent)` summary
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} used ({}%)",
+            format_bytes(self.used),
+            format_bytes(self.quota),
+            (self.used * 100).checked_div(self.quota).unwrap_or(100)
+        )
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+/// Returns the backup storage quota of a guild in bytes
+pub async fn guild_quota(pool: &sqlx::PgPool, guild_id: GuildId) -> Result<u64, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct QuotaRecord {
+        quota_mb: i64,
+    }
+
+    let rec: Option<QuotaRecord> =
+        sqlx::query_as("SELECT quota_mb FROM backups__quotas WHERE guild_id = $1")
+            .bind(guild_id.to_string())
+            .fetch_optional(pool)
+            .await?;
+
+    let quota_mb = match rec {
+        Some(rec) => rec.quota_mb.max(0) as u64,
+        None => CONFIG.backups.default_quota_mb,
+    };
+
+    Ok(quota_mb * 1024 * 1024)
+}
+
+/// Sets the backup storage quota of a guild in megabytes, or resets it to the default if `None`
+pub async fn set_guild_quota(
+    pool: &sqlx::PgPool,
+    guild_id: GuildId,
+    quota_mb: Option<u64>,
+) -> Result<(), crate::Error> {
+    match quota_mb {
+        Some(quota_mb) => {
+            sqlx::query(
+                "INSERT INTO backups__quotas (guild_id, quota_mb) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET quota_mb = EXCLUDED.quota_mb",
+            )
+            .bind(guild_id.to_string())
+            .bind(i64::try_from(quota_mb).map_err(|_| "Quota is too large")?)
+            .execute(pool)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM backups__quotas WHERE guild_id = $1")
+                .bind(guild_id.to_string())
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the size of a job output in the object store
+///
+/// Only used for backups whose size was not recorded by the checksum task
+async fn output_size(data: &Data, job: &jobserver::Job) -> Result<u64, crate::Error> {
+    let Some(path) = job.get_file_path() else {
+        return Ok(0);
+    };
+
+    let url = data
+        .object_store
+        .get_url(
+            &guild_bucket(job.guild_id),
+            &path,
+            std::time::Duration::from_secs(60),
+        )
+        .await?;
+
+    // Presigned URLs only allow GET, so fetch a single byte and read the total size from Content-Range
+    let resp = data
+        .reqwest
+        .get(&url)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    if let Some(total) = resp
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|v| v.parse::<u64>().ok())
+    {
+        return Ok(total);
+    }
+
+    resp.content_length()
+        .ok_or_else(|| "Object store did not return the size of the backup".into())
+}
+
+/// Computes the backup storage used by a guild
+///
+/// Sizes are recorded in `backups__job_metadata` by the checksum task, so only backups it has not seen yet hit the object store
+pub async fn guild_usage(data: &Data, guild_id: GuildId) -> Result<GuildUsage, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct SizeRecord {
+        job_id: Uuid,
+        size_bytes: Option<i64>,
+    }
+
+    let jobs = jobserver::Job::from_guild_and_name(guild_id, "guild_create_backup", &data.pool)
+        .await
+        .map_err(|e| format!("Failed to get backup jobs: {}", e))?;
+
+    let sizes: HashMap<Uuid, Option<i64>> = sqlx::query_as::<_, SizeRecord>(
+        "SELECT job_id, size_bytes FROM backups__job_metadata WHERE guild_id = $1",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(&data.pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.job_id, r.size_bytes))
+    .collect();
+
+    let mut backups = Vec::new();
+    for job in jobs.iter().filter(|j| j.output.is_some()) {
+        let size = match sizes.get(&job.id).copied().flatten() {
+            Some(size) => size.max(0) as u64,
+            None => {
+                let size = match output_size(data, job).await {
+                    Ok(size) => size,
+                    Err(e) => {
+                        log::warn!("Failed to get size of backup {}: {}", job.id, e);
+                        continue;
+                    }
+                };
+
+                sqlx::query(
+                    "INSERT INTO backups__job_metadata (job_id, guild_id, size_bytes) VALUES ($1, $2, $3) ON CONFLICT (job_id) DO UPDATE SET size_bytes = EXCLUDED.size_bytes",
+                )
+                .bind(job.id)
+                .bind(guild_id.to_string())
+                .bind(size as i64)
+                .execute(&data.pool)
+                .await?;
+
+                size
+            }
+        };
+
+        backups.push((job.id, size));
+    }
+
+    backups.sort_by(|a, b| b.1.cmp(&a.1));
+
+    Ok(GuildUsage {
+        used: backups.iter().map(|(_, size)| size).sum(),
+        quota: guild_quota(&data.pool, guild_id).await?,
+        backups,
+    })
+}
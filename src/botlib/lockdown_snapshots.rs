@@ -0,0 +1,558 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ChannelId, EditRole, GuildId, Http, Mentionable, PermissionOverwrite, PermissionOverwriteType,
+    Permissions, RoleId,
+};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/// The kind of a stored permission snapshot
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SnapshotKind {
+    /// The state of the guild before the first lockdown of a set was applied
+    Baseline,
+    /// The state of the guild after the last lockdown apply/remove made by AntiRaid
+    Expected,
+}
+
+impl SnapshotKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotKind::Baseline => "baseline",
+            SnapshotKind::Expected => "expected",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChannelSnapshot {
+    pub id: ChannelId,
+    pub overwrites: Vec<PermissionOverwrite>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleSnapshot {
+    pub id: RoleId,
+    pub permissions: Permissions,
+}
+
+/// The channel overwrites and role permissions of a guild at a point in time
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuildPermissionSnapshot {
+    pub channels: Vec<ChannelSnapshot>,
+    pub roles: Vec<RoleSnapshot>,
+}
+
+/// A snapshot as stored, along with when it was recorded
+pub struct StoredSnapshot {
+    pub snapshot: GuildPermissionSnapshot,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoredSnapshot {
+    /// Whether a lockdown was applied after the snapshot was recorded
+    ///
+    /// Lockdowns applied outside of the bot's commands (e.g. by a template or the website), or whose snapshot
+    /// failed to save, are not part of the snapshot. Verifying against it would report them as drift and
+    /// repairing would undo them, so stale snapshots must never be repaired to
+    pub fn is_stale(&self, lockdowns: &[lockdowns::Lockdown]) -> bool {
+        lockdowns.iter().any(|l| l.created_at > self.created_at)
+    }
+}
+
+/// The channels and roles whose permissions are managed by lockdowns
+///
+/// Drift outside of this scope (e.g. a channel created or edited by a moderator during a lockdown) is not reported
+#[derive(Default)]
+pub struct ManagedScope {
+    pub channels: HashSet<ChannelId>,
+    pub roles: HashSet<RoleId>,
+}
+
+impl ManagedScope {
+    /// Returns the channels and roles managed by the active lockdowns which were applied by `recorded_at`
+    ///
+    /// Lockdowns applied later are not part of a snapshot recorded at that time, so their channels and
+    /// roles are left out
+    pub async fn from_lockdowns(
+        lockdowns: &[lockdowns::Lockdown],
+        recorded_at: DateTime<Utc>,
+    ) -> Result<Self, crate::Error> {
+        let mut scope = Self::default();
+
+        for lockdown in lockdowns.iter().filter(|l| l.created_at <= recorded_at) {
+            let shared = lockdown
+                .r#type
+                .shareable(&lockdown.data)
+                .await
+                .map_err(|e| format!("Failed to read data of lockdown {}: {}", lockdown.id, e))?;
+
+            scope
+                .channels
+                .extend(shared.channel_permissions.into_keys());
+            scope.roles.extend(shared.role_permissions.into_keys());
+        }
+
+        Ok(scope)
+    }
+
+    /// Returns the channels and roles that differ between two snapshots
+    ///
+    /// Used once all lockdowns are removed to find what they had changed from the baseline
+    pub fn changed_between(
+        before: &GuildPermissionSnapshot,
+        after: &GuildPermissionSnapshot,
+    ) -> Self {
+        let mut scope = Self::default();
+
+        for after_channel in &after.channels {
+            let Some(before_channel) = before.channels.iter().find(|c| c.id == after_channel.id)
+            else {
+                continue;
+            };
+
+            let unchanged = before_channel.overwrites.len() == after_channel.overwrites.len()
+                && before_channel.overwrites.iter().all(|b| {
+                    after_channel
+                        .overwrites
+                        .iter()
+                        .any(|a| a.kind == b.kind && a.allow == b.allow && a.deny == b.deny)
+                });
+
+            if !unchanged {
+                scope.channels.insert(after_channel.id);
+            }
+        }
+
+        for after_role in &after.roles {
+            if before
+                .roles
+                .iter()
+                .any(|r| r.id == after_role.id && r.permissions != after_role.permissions)
+            {
+                scope.roles.insert(after_role.id);
+            }
+        }
+
+        scope
+    }
+}
+
+/// A difference between a stored snapshot and the current state of a guild
+#[derive(Clone)]
+pub enum Drift {
+    Overwrite {
+        channel_id: ChannelId,
+        kind: PermissionOverwriteType,
+        expected: Option<PermissionOverwrite>,
+        actual: Option<PermissionOverwrite>,
+    },
+    Role {
+        role_id: RoleId,
+        expected: Permissions,
+        actual: Permissions,
+    },
+}
+
+fn overwrite_target(kind: &PermissionOverwriteType) -> String {
+    match kind {
+        PermissionOverwriteType::Member(user_id) => user_id.mention().to_string(),
+        PermissionOverwriteType::Role(role_id) => role_id.mention().to_string(),
+        _ => "unknown target".to_string(),
+    }
+}
+
+fn overwrite_perms(ow: &Option<PermissionOverwrite>) -> String {
+    match ow {
+        Some(ow) => format!("allow `{}`, deny `{}`", ow.allow, ow.deny),
+        None => "no overwrite".to_string(),
+    }
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::Overwrite {
+                channel_id,
+                kind,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} overwrite for {}: expected {}, found {}",
+                channel_id.mention(),
+                overwrite_target(kind),
+                overwrite_perms(expected),
+                overwrite_perms(actual)
+            ),
+            Drift::Role {
+                role_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} permissions: expected `{}`, found `{}`",
+                role_id.mention(),
+                expected,
+                actual
+            ),
+        }
+    }
+}
+
+/// Captures the current channel overwrites and role permissions of a guild
+pub async fn capture(
+    http: &Http,
+    guild_id: GuildId,
+) -> Result<GuildPermissionSnapshot, crate::Error> {
+    let channels = http.get_channels(guild_id).await?;
+    let roles = http.get_guild_roles(guild_id).await?;
+
+    Ok(GuildPermissionSnapshot {
+        channels: channels
+            .into_iter()
+            .map(|c| ChannelSnapshot {
+                id: c.id,
+                overwrites: c.permission_overwrites.iter().cloned().collect(),
+            })
+            .collect(),
+        roles: roles
+            .into_iter()
+            .map(|r| RoleSnapshot {
+                id: r.id,
+                permissions: r.permissions,
+            })
+            .collect(),
+    })
+}
+
+/// Saves a snapshot of the given kind, replacing any previous one
+pub async fn save(
+    pool: &PgPool,
+    guild_id: GuildId,
+    kind: SnapshotKind,
+    snapshot: &GuildPermissionSnapshot,
+) -> Result<(), crate::Error> {
+    sqlx::query(
+        "INSERT INTO lockdown__guild_snapshots (guild_id, kind, snapshot) VALUES ($1, $2, $3) ON CONFLICT (guild_id, kind) DO UPDATE SET snapshot = EXCLUDED.snapshot, created_at = NOW()",
+    )
+    .bind(guild_id.to_string())
+    .bind(kind.as_str())
+    .bind(serde_json::to_value(snapshot)?)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Loads the snapshot of the given kind, if any
+pub async fn load(
+    pool: &PgPool,
+    guild_id: GuildId,
+    kind: SnapshotKind,
+) -> Result<Option<StoredSnapshot>, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct SnapshotRecord {
+        snapshot: serde_json::Value,
+        created_at: DateTime<Utc>,
+    }
+
+    let rec: Option<SnapshotRecord> = sqlx::query_as(
+        "SELECT snapshot, created_at FROM lockdown__guild_snapshots WHERE guild_id = $1 AND kind = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(kind.as_str())
+    .fetch_optional(pool)
+    .await?;
+
+    match rec {
+        Some(rec) => Ok(Some(StoredSnapshot {
+            snapshot: serde_json::from_value(rec.snapshot)?,
+            created_at: rec.created_at,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Deletes the snapshot of the given kind
+pub async fn delete(
+    pool: &PgPool,
+    guild_id: GuildId,
+    kind: SnapshotKind,
+) -> Result<(), crate::Error> {
+    sqlx::query("DELETE FROM lockdown__guild_snapshots WHERE guild_id = $1 AND kind = $2")
+        .bind(guild_id.to_string())
+        .bind(kind.as_str())
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records the current permission state of the guild for later verification
+///
+/// Errors are only logged as the lockdown itself has already been applied/removed at this point
+pub async fn record(http: &Http, pool: &PgPool, guild_id: GuildId, kind: SnapshotKind) {
+    let snapshot = match capture(http, guild_id).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            log::error!("Failed to capture {} snapshot: {}", kind.as_str(), e);
+            return;
+        }
+    };
+
+    if let Err(e) = save(pool, guild_id, kind, &snapshot).await {
+        log::error!("Failed to save {} snapshot: {}", kind.as_str(), e);
+    }
+}
+
+/// Updates the stored snapshots after a lockdown has been removed
+pub async fn record_after_remove(http: &Http, pool: &PgPool, guild_id: GuildId, now_empty: bool) {
+    // Once all lockdowns are removed, the baseline and the last expected snapshot are both kept so
+    // that `lockdowns verify` can find channels the lockdowns changed that were not fully restored
+    if !now_empty {
+        record(http, pool, guild_id, SnapshotKind::Expected).await;
+    }
+}
+
+/// Returns the differences between an expected snapshot and the actual state within the managed scope
+///
+/// Channels and roles which no longer exist (or did not exist when the snapshot was taken) are ignored
+pub fn diff(
+    expected: &GuildPermissionSnapshot,
+    actual: &GuildPermissionSnapshot,
+    scope: &ManagedScope,
+) -> Vec<Drift> {
+    let mut drifts = Vec::new();
+
+    for expected_channel in &expected.channels {
+        if !scope.channels.contains(&expected_channel.id) {
+            continue;
+        }
+
+        let Some(actual_channel) = actual.channels.iter().find(|c| c.id == expected_channel.id)
+        else {
+            continue;
+        };
+
+        for ow in &expected_channel.overwrites {
+            let actual_ow = actual_channel.overwrites.iter().find(|a| a.kind == ow.kind);
+
+            let matches = match actual_ow {
+                Some(a) => a.allow == ow.allow && a.deny == ow.deny,
+                None => false,
+            };
+
+            if !matches {
+                drifts.push(Drift::Overwrite {
+                    channel_id: expected_channel.id,
+                    kind: ow.kind,
+                    expected: Some(ow.clone()),
+                    actual: actual_ow.cloned(),
+                });
+            }
+        }
+
+        for ow in &actual_channel.overwrites {
+            if !expected_channel
+                .overwrites
+                .iter()
+                .any(|e| e.kind == ow.kind)
+            {
+                drifts.push(Drift::Overwrite {
+                    channel_id: expected_channel.id,
+                    kind: ow.kind,
+                    expected: None,
+                    actual: Some(ow.clone()),
+                });
+            }
+        }
+    }
+
+    for expected_role in &expected.roles {
+        if !scope.roles.contains(&expected_role.id) {
+            continue;
+        }
+
+        let Some(actual_role) = actual.roles.iter().find(|r| r.id == expected_role.id) else {
+            continue;
+        };
+
+        if actual_role.permissions != expected_role.permissions {
+            drifts.push(Drift::Role {
+                role_id: expected_role.id,
+                expected: expected_role.permissions,
+                actual: actual_role.permissions,
+            });
+        }
+    }
+
+    drifts
+}
+
+/// Attempts to bring the guild back in line with the expected state, returning the drifts that could not be repaired
+pub async fn repair(
+    http: &Http,
+    guild_id: GuildId,
+    drifts: Vec<Drift>,
+    reason: &str,
+) -> Vec<(Drift, crate::Error)> {
+    let mut failed = Vec::new();
+
+    for drift in drifts {
+        let res: Result<(), crate::Error> = match drift {
+            Drift::Overwrite {
+                channel_id,
+                kind,
+                ref expected,
+                ..
+            } => match expected {
+                Some(expected) => channel_id
+                    .create_permission(http, expected.clone(), Some(reason))
+                    .await
+                    .map_err(|e| e.into()),
+                None => channel_id
+                    .delete_permission(http, kind, Some(reason))
+                    .await
+                    .map_err(|e| e.into()),
+            },
+            Drift::Role {
+                role_id, expected, ..
+            } => guild_id
+                .edit_role(
+                    http,
+                    role_id,
+                    EditRole::new()
+                        .permissions(expected)
+                        .audit_log_reason(reason),
+                )
+                .await
+                .map(|_| ())
+                .map_err(|e| e.into()),
+        };
+
+        if let Err(e) = res {
+            failed.push((drift, e));
+        }
+    }
+
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overwrite(role_id: u64, deny: Permissions) -> PermissionOverwrite {
+        PermissionOverwrite {
+            allow: Permissions::empty(),
+            deny,
+            kind: PermissionOverwriteType::Role(RoleId::new(role_id)),
+        }
+    }
+
+    fn snapshot(
+        overwrites: Vec<PermissionOverwrite>,
+        role_perms: Permissions,
+    ) -> GuildPermissionSnapshot {
+        GuildPermissionSnapshot {
+            channels: vec![ChannelSnapshot {
+                id: ChannelId::new(1),
+                overwrites,
+            }],
+            roles: vec![RoleSnapshot {
+                id: RoleId::new(2),
+                permissions: role_perms,
+            }],
+        }
+    }
+
+    fn full_scope() -> ManagedScope {
+        ManagedScope {
+            channels: HashSet::from([ChannelId::new(1)]),
+            roles: HashSet::from([RoleId::new(2)]),
+        }
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let s = snapshot(
+            vec![overwrite(2, Permissions::SEND_MESSAGES)],
+            Permissions::VIEW_CHANNEL,
+        );
+
+        assert!(diff(&s, &s.clone(), &full_scope()).is_empty());
+    }
+
+    #[test]
+    fn test_diff_changed_missing_and_extra() {
+        let expected = snapshot(
+            vec![overwrite(2, Permissions::SEND_MESSAGES)],
+            Permissions::VIEW_CHANNEL,
+        );
+        let actual = snapshot(
+            vec![overwrite(3, Permissions::SEND_MESSAGES)],
+            Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+        );
+
+        let drifts = diff(&expected, &actual, &full_scope());
+        assert_eq!(drifts.len(), 3);
+        assert!(drifts.iter().any(|d| matches!(
+            d,
+            Drift::Overwrite {
+                expected: Some(_),
+                actual: None,
+                ..
+            }
+        )));
+        assert!(drifts.iter().any(|d| matches!(
+            d,
+            Drift::Overwrite {
+                expected: None,
+                actual: Some(_),
+                ..
+            }
+        )));
+        assert!(drifts.iter().any(|d| matches!(d, Drift::Role { .. })));
+    }
+
+    #[test]
+    fn test_diff_outside_scope() {
+        let expected = snapshot(
+            vec![overwrite(2, Permissions::SEND_MESSAGES)],
+            Permissions::VIEW_CHANNEL,
+        );
+        let actual = snapshot(vec![], Permissions::empty());
+
+        assert!(diff(&expected, &actual, &ManagedScope::default()).is_empty());
+    }
+
+    #[test]
+    fn test_changed_between() {
+        let before = snapshot(
+            vec![overwrite(2, Permissions::empty())],
+            Permissions::VIEW_CHANNEL,
+        );
+
+        let unchanged = ManagedScope::changed_between(&before, &before.clone());
+        assert!(unchanged.channels.is_empty());
+        assert!(unchanged.roles.is_empty());
+
+        let after = snapshot(
+            vec![overwrite(2, Permissions::SEND_MESSAGES)],
+            Permissions::VIEW_CHANNEL,
+        );
+        let changed = ManagedScope::changed_between(&before, &after);
+        assert!(changed.channels.contains(&ChannelId::new(1)));
+        assert!(changed.roles.is_empty());
+
+        let after = snapshot(
+            vec![overwrite(2, Permissions::empty())],
+            Permissions::empty(),
+        );
+        let changed = ManagedScope::changed_between(&before, &after);
+        assert!(changed.channels.is_empty());
+        assert!(changed.roles.contains(&RoleId::new(2)));
+    }
+}
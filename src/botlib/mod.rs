@@ -1,8 +1,22 @@
+pub mod antiraidconfig;
+pub mod backupdiff;
+pub mod backupfile;
+pub mod backupusage;
 pub mod canonical;
 pub mod durationstring;
+pub mod lockdown_snapshots;
 pub mod numericlistparser;
 pub mod permission_checks;
+pub mod restoreplan;
+pub mod settings;
 pub mod specialchannelallocs;
+pub mod templatecaps;
+pub mod templateconfig;
+pub mod templateerrors;
+pub mod templateexport;
+pub mod templateversions;
+pub mod text;
+pub mod vcl;
 
 use silverpelt::data::Data;
 
@@ -0,0 +1,186 @@
+use super::backupfile::{BackupChannel, BackupGuild, BackupRole};
+use std::fmt::Write;
+
+/// The changes a restore is expected to make to a guild
+#[derive(Default)]
+pub struct RestorePlan {
+    pub channels_deleted: Vec<String>,
+    pub channels_created: Vec<String>,
+    pub channels_modified: Vec<String>,
+    pub roles_deleted: Vec<String>,
+    pub roles_created: Vec<String>,
+    pub roles_modified: Vec<String>,
+}
+
+fn describe_channel(channel: &BackupChannel) -> String {
+    format!("#{} ({})", channel.name, channel.id)
+}
+
+fn describe_role(role: &BackupRole) -> String {
+    format!("@{} ({})", role.name, role.id)
+}
+
+fn overwrites_differ(backup: &BackupChannel, live: &BackupChannel) -> bool {
+    if backup.permission_overwrites.len() != live.permission_overwrites.len() {
+        return true;
+    }
+
+    backup.permission_overwrites.iter().any(|ow| {
+        !live
+            .permission_overwrites
+            .iter()
+            .any(|l| l.id == ow.id && l.kind == ow.kind && l.allow == ow.allow && l.deny == ow.deny)
+    })
+}
+
+fn channel_differs(backup: &BackupChannel, live: &BackupChannel) -> bool {
+    backup.name != live.name
+        || backup.parent_id != live.parent_id
+        || backup.position != live.position
+        || overwrites_differ(backup, live)
+}
+
+fn role_differs(backup: &BackupRole, live: &BackupRole) -> bool {
+    backup.name != live.name || backup.permissions != live.permissions || backup.color != live.color
+}
+
+/// The options of a restore which affect what it changes
+pub struct RestorePlanOptions<'a> {
+    /// The channel restore mode (full, partial or none)
+    pub channel_restore_mode: &'a str,
+    /// The role restore mode (only full is supported by the jobserver)
+    pub role_restore_mode: &'a str,
+    pub protected_channels: &'a [String],
+    pub protected_roles: &'a [String],
+    /// Restored channels and roles get new IDs and so never match existing ones
+    pub remap_ids: bool,
+}
+
+impl RestorePlanOptions<'_> {
+    /// Returns whether a channel is within the set of channels being restored
+    fn in_scope(&self, channel: &BackupChannel) -> bool {
+        !self.protected_channels.contains(&channel.id.to_string())
+    }
+}
+
+impl RestorePlan {
+    /// Computes what a restore of `backup` onto `live` will do
+    ///
+    /// `full` deletes everything that is not protected before recreating the backup. For channels, `partial` only creates missing and updates existing channels and `none` leaves them untouched
+    pub fn new(
+        backup: &BackupGuild,
+        live: &BackupGuild,
+        guild_id: serenity::all::GuildId,
+        opts: &RestorePlanOptions,
+    ) -> Self {
+        let mut plan = Self::default();
+
+        match opts.channel_restore_mode {
+            "full" => {
+                for channel in live.channels.iter().filter(|c| opts.in_scope(c)) {
+                    plan.channels_deleted.push(describe_channel(channel));
+                }
+
+                for channel in backup.channels.iter().filter(|c| opts.in_scope(c)) {
+                    plan.channels_created.push(describe_channel(channel));
+                }
+            }
+            "partial" => {
+                for channel in backup.channels.iter().filter(|c| opts.in_scope(c)) {
+                    match live
+                        .channels
+                        .iter()
+                        .find(|l| !opts.remap_ids && l.id == channel.id)
+                    {
+                        Some(existing) => {
+                            if channel_differs(channel, existing) {
+                                plan.channels_modified.push(describe_channel(channel));
+                            }
+                        }
+                        None => plan.channels_created.push(describe_channel(channel)),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let role_restore_mode = opts.role_restore_mode;
+        let protected_roles = opts.protected_roles;
+
+        // The @everyone role and managed (bot/integration) roles can never be deleted or created, only edited
+        let is_fixed_role = |r: &BackupRole| r.id.get() == guild_id.get() || r.managed;
+        let is_protected_role = |r: &BackupRole| {
+            is_fixed_role(r) || protected_roles.iter().any(|p| *p == r.id.to_string())
+        };
+
+        if role_restore_mode == "full" {
+            for role in live.roles.iter().filter(|r| !is_protected_role(r)) {
+                plan.roles_deleted.push(describe_role(role));
+            }
+
+            for role in &backup.roles {
+                // The @everyone role of a backup from another server maps onto this server's @everyone role
+                let is_everyone = role.name == "@everyone" && role.position == 0;
+
+                let existing = live.roles.iter().find(|l| {
+                    if is_everyone {
+                        l.id.get() == guild_id.get()
+                    } else {
+                        !opts.remap_ids && l.id == role.id
+                    }
+                });
+
+                if is_everyone || is_fixed_role(role) {
+                    if let Some(existing) = existing {
+                        if role_differs(role, existing) {
+                            plan.roles_modified.push(describe_role(role));
+                        }
+                    }
+                    continue;
+                }
+
+                if protected_roles.iter().any(|p| *p == role.id.to_string()) {
+                    continue;
+                }
+
+                plan.roles_created.push(describe_role(role));
+            }
+        }
+
+        plan
+    }
+
+    fn sections(&self) -> [(&'static str, &Vec<String>); 6] {
+        [
+            ("Channels Deleted", &self.channels_deleted),
+            ("Channels Created", &self.channels_created),
+            ("Channels Modified", &self.channels_modified),
+            ("Roles Deleted", &self.roles_deleted),
+            ("Roles Created", &self.roles_created),
+            ("Roles Modified", &self.roles_modified),
+        ]
+    }
+
+    /// Returns the number of changes in each section
+    pub fn summary(&self) -> Vec<(&'static str, usize)> {
+        self.sections()
+            .iter()
+            .map(|(name, v)| (*name, v.len()))
+            .collect()
+    }
+
+    /// Renders the full plan as plain text
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (name, changes) in self.sections() {
+            let _ = writeln!(text, "== {} ({}) ==", name, changes.len());
+            for change in changes {
+                let _ = writeln!(text, "{}", change);
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+}
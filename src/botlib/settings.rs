@@ -0,0 +1,143 @@
+use ar_settings::types::{OperationType, Setting};
+use indexmap::IndexMap;
+use serde_json::Value;
+use serenity::all::{GuildId, UserId};
+use silverpelt::data::Data;
+
+/// Why a settings operation could not be run
+pub enum SettingsOperationError {
+    /// No setting with the given ID exists
+    NotFound(String),
+    /// The setting does not declare the operation
+    Unsupported(String),
+    /// The user lacks a permission the operation needs
+    Forbidden(String),
+    /// The request is missing something the operation needs
+    BadRequest(String),
+    /// The operation itself failed
+    Failed(String),
+}
+
+/// Returns the kittycat permissions an operation on a setting needs
+///
+/// Each operation a setting supports declares the command it corresponds to, so the operation needs the same
+/// permissions as that command in [`crate::bot::command_permissions_metadata`]
+pub fn operation_permissions(
+    setting: &Setting,
+    op: &OperationType,
+    command_permissions: &IndexMap<String, Vec<String>>,
+) -> Result<Vec<String>, SettingsOperationError> {
+    let Some(spec) = setting.operations.get(op) else {
+        return Err(SettingsOperationError::Unsupported(format!(
+            "Setting {} does not support this operation",
+            setting.id
+        )));
+    };
+
+    // A command without permissions would let anyone run the operation, so it is refused instead
+    match command_permissions.get(spec.corresponding_command) {
+        Some(perms) if !perms.is_empty() => Ok(perms.clone()),
+        _ => Err(SettingsOperationError::Unsupported(format!(
+            "Setting {} has no permissions declared for this operation",
+            setting.id
+        ))),
+    }
+}
+
+/// Returns all settings which can be operated on over RPC
+///
+/// Settings are added here as commands gain an `ar_settings` equivalent, none have one yet
+pub fn settings() -> Vec<Setting> {
+    Vec::new()
+}
+
+/// The state ar_settings operations run with
+///
+/// The fields of `SettingsData`, the `Setting::operations` map and the `ar_settings::cfg::settings_*`
+/// signatures used in this file follow the `Anti-Raid/settings` crate but could not be checked against it,
+/// so they must be verified when it is updated
+fn settings_data(
+    data: &Data,
+    serenity_context: &serenity::all::Context,
+) -> ar_settings::types::SettingsData {
+    ar_settings::types::SettingsData {
+        pool: data.pool.clone(),
+        reqwest: data.reqwest.clone(),
+        object_store: data.object_store.clone(),
+        cache_http: botox::cache::CacheHttpImpl::from_ctx(serenity_context),
+        serenity_context: serenity_context.clone(),
+    }
+}
+
+/// Runs an operation on a setting on behalf of a user after checking the permissions the setting declares for it
+pub async fn run_operation(
+    data: &Data,
+    serenity_context: &serenity::all::Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    setting_id: &str,
+    op: OperationType,
+    fields: IndexMap<String, Value>,
+) -> Result<Vec<IndexMap<String, Value>>, SettingsOperationError> {
+    let Some(setting) = settings().into_iter().find(|s| s.id == setting_id) else {
+        return Err(SettingsOperationError::NotFound(format!(
+            "Setting {} not found",
+            setting_id
+        )));
+    };
+
+    let perms = operation_permissions(&setting, &op, &crate::bot::command_permissions_metadata())?;
+
+    for perm in perms {
+        crate::botlib::permission_checks::check_permissions(
+            guild_id,
+            user_id,
+            &data.pool,
+            serenity_context,
+            &data.reqwest,
+            &None,
+            kittycat::perms::Permission::from_string(&perm),
+        )
+        .await
+        .map_err(|e| SettingsOperationError::Forbidden(e.to_string()))?;
+    }
+
+    let settings_data = settings_data(data, serenity_context);
+
+    let res = match op {
+        OperationType::View => {
+            ar_settings::cfg::settings_view(&setting, &settings_data, guild_id, user_id, fields)
+                .await
+        }
+        OperationType::Create => {
+            ar_settings::cfg::settings_create(&setting, &settings_data, guild_id, user_id, fields)
+                .await
+                .map(|row| vec![row])
+        }
+        OperationType::Update => {
+            ar_settings::cfg::settings_update(&setting, &settings_data, guild_id, user_id, fields)
+                .await
+                .map(|row| vec![row])
+        }
+        OperationType::Delete => {
+            let Some(pkey) = fields.get(&setting.primary_key) else {
+                return Err(SettingsOperationError::BadRequest(format!(
+                    "Missing primary key {}",
+                    setting.primary_key
+                )));
+            };
+
+            ar_settings::cfg::settings_delete(
+                &setting,
+                &settings_data,
+                guild_id,
+                user_id,
+                pkey.clone(),
+            )
+            .await
+            .map(|row| vec![row])
+        }
+    };
+
+    res.map_err(|e| SettingsOperationError::Failed(e.to_string()))
+}
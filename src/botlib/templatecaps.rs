@@ -0,0 +1,104 @@
+/// Keywords marking a capability as dangerous, i.e. able to take destructive or moderation actions or reach outside of Discord
+const DANGEROUS_CAPABILITY_KEYWORDS: &[&str] = &[
+    "*",
+    "ban",
+    "kick",
+    "timeout",
+    "delete",
+    "lockdown",
+    "webhook",
+    "http",
+    "role",
+    "permission",
+];
+
+pub fn is_dangerous(cap: &str) -> bool {
+    let cap = cap.to_lowercase();
+    DANGEROUS_CAPABILITY_KEYWORDS
+        .iter()
+        .any(|keyword| cap.contains(keyword))
+}
+
+/// The capability and event changes between an installed template and the version being loaded
+pub struct CapabilityDiff {
+    pub added_caps: Vec<String>,
+    pub removed_caps: Vec<String>,
+    pub kept_caps: Vec<String>,
+    pub added_events: Vec<String>,
+    pub removed_events: Vec<String>,
+}
+
+impl CapabilityDiff {
+    /// `installed` is the (capabilities, events) of the currently installed version, if any
+    pub fn new(
+        installed: Option<(&[String], &[String])>,
+        new_caps: &[String],
+        new_events: &[String],
+    ) -> Self {
+        let (old_caps, old_events) = installed.unwrap_or((&[], &[]));
+
+        Self {
+            added_caps: new_caps
+                .iter()
+                .filter(|c| !old_caps.contains(c))
+                .cloned()
+                .collect(),
+            removed_caps: old_caps
+                .iter()
+                .filter(|c| !new_caps.contains(c))
+                .cloned()
+                .collect(),
+            kept_caps: new_caps
+                .iter()
+                .filter(|c| old_caps.contains(c))
+                .cloned()
+                .collect(),
+            added_events: new_events
+                .iter()
+                .filter(|e| !old_events.contains(e))
+                .cloned()
+                .collect(),
+            removed_events: old_events
+                .iter()
+                .filter(|e| !new_events.contains(e))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// All capabilities the new version asks for
+    pub fn requested_caps(&self) -> Vec<String> {
+        self.added_caps
+            .iter()
+            .chain(self.kept_caps.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Formats a list of capabilities or events as an embed field value of at most `max_len` characters,
+/// flagging dangerous capabilities
+pub fn field_value(items: &[String], flag_dangerous: bool, max_len: usize) -> String {
+    if items.is_empty() {
+        return "None".to_string();
+    }
+
+    let mut value = String::new();
+    for (i, item) in items.iter().enumerate() {
+        let line = if flag_dangerous && is_dangerous(item) {
+            format!(":warning: `{}`\n", item)
+        } else {
+            format!("`{}`\n", item)
+        };
+
+        // Leaves room for the "...and N more" suffix
+        if value.len() + line.len() > max_len.saturating_sub(24) {
+            value += &format!("...and {} more", items.len() - i);
+            break;
+        }
+
+        value += &line;
+    }
+
+    value
+}
@@ -0,0 +1,371 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Select menus allow at most 25 options
+const MAX_CHOICES: usize = 25;
+
+/// A configuration value a shop template asks for when it is loaded
+///
+/// Schemas are stored in `template_shop.config_schema` as a JSON array of fields, for example
+/// `[{"id": "log_channel", "label": "Log Channel", "type": "channel", "required": true}]`
+///
+/// The values are kept in `guild_templates.config` and reach the template through [`CONFIG_MODULE`], which is
+/// added to a copy of the shop's files stored with the `guild_templates` row
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConfigField {
+    pub id: String,
+    pub label: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub default: Option<Value>,
+    #[serde(flatten)]
+    pub kind: ConfigFieldKind,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigFieldKind {
+    String {
+        #[serde(default)]
+        max_length: Option<usize>,
+    },
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    Boolean,
+    Channel,
+    Role,
+    Choice {
+        choices: Vec<String>,
+    },
+}
+
+impl ConfigField {
+    /// Whether the field is filled in through a select menu rather than a modal
+    pub fn is_select(&self) -> bool {
+        matches!(
+            self.kind,
+            ConfigFieldKind::Boolean
+                | ConfigFieldKind::Channel
+                | ConfigFieldKind::Role
+                | ConfigFieldKind::Choice { .. }
+        )
+    }
+
+    /// Parses and validates a raw value entered by the user
+    pub fn parse(&self, raw: &str) -> Result<Value, String> {
+        let raw = raw.trim();
+
+        match &self.kind {
+            ConfigFieldKind::String { max_length } => {
+                if let Some(max_length) = max_length {
+                    if raw.chars().count() > *max_length {
+                        return Err(format!(
+                            "{} must be at most {} characters",
+                            self.label, max_length
+                        ));
+                    }
+                }
+
+                Ok(Value::String(raw.to_string()))
+            }
+            ConfigFieldKind::Integer { min, max } => {
+                let value = raw
+                    .parse::<i64>()
+                    .map_err(|_| format!("{} must be a whole number", self.label))?;
+
+                if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                    return Err(format!(
+                        "{} must be between {} and {}",
+                        self.label,
+                        min.map(|m| m.to_string()).unwrap_or("-∞".to_string()),
+                        max.map(|m| m.to_string()).unwrap_or("∞".to_string())
+                    ));
+                }
+
+                Ok(Value::from(value))
+            }
+            ConfigFieldKind::Number { min, max } => {
+                let value = raw
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or_else(|| format!("{} must be a number", self.label))?;
+
+                if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                    return Err(format!(
+                        "{} must be between {} and {}",
+                        self.label,
+                        min.map(|m| m.to_string()).unwrap_or("-∞".to_string()),
+                        max.map(|m| m.to_string()).unwrap_or("∞".to_string())
+                    ));
+                }
+
+                Ok(Value::from(value))
+            }
+            ConfigFieldKind::Boolean => match raw.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "off" | "0" => Ok(Value::Bool(false)),
+                _ => Err(format!("{} must be true or false", self.label)),
+            },
+            ConfigFieldKind::Channel | ConfigFieldKind::Role => {
+                raw.parse::<u64>()
+                    .map_err(|_| format!("{} must be a valid ID", self.label))?;
+
+                Ok(Value::String(raw.to_string()))
+            }
+            ConfigFieldKind::Choice { choices } => {
+                if !choices.iter().any(|c| c == raw) {
+                    return Err(format!(
+                        "{} must be one of: {}",
+                        self.label,
+                        choices.join(", ")
+                    ));
+                }
+
+                Ok(Value::String(raw.to_string()))
+            }
+        }
+    }
+
+    /// Validates a stored value, such as a default or a value kept from an older version of the template
+    pub fn check(&self, value: &Value) -> Result<Value, String> {
+        let raw = match (&self.kind, value) {
+            (
+                ConfigFieldKind::Integer { .. } | ConfigFieldKind::Number { .. },
+                Value::Number(n),
+            ) => n.to_string(),
+            (ConfigFieldKind::Boolean, Value::Bool(b)) => b.to_string(),
+            (
+                ConfigFieldKind::String { .. }
+                | ConfigFieldKind::Channel
+                | ConfigFieldKind::Role
+                | ConfigFieldKind::Choice { .. },
+                Value::String(s),
+            ) => s.clone(),
+            _ => return Err(format!("{} has a value of the wrong type", self.label)),
+        };
+
+        self.parse(&raw)
+    }
+
+    /// Formats a stored value for display
+    pub fn display(&self, value: &Value) -> String {
+        match (&self.kind, value) {
+            (ConfigFieldKind::Channel, Value::String(id)) => format!("<#{}>", id),
+            (ConfigFieldKind::Role, Value::String(id)) => format!("<@&{}>", id),
+            (_, Value::String(s)) => format!("`{}`", s.replace('`', "\\`")),
+            (_, v) => format!("`{}`", v),
+        }
+    }
+}
+
+/// Parses the config schema of a shop template
+pub fn parse_schema(schema: Value) -> Result<Vec<ConfigField>, crate::Error> {
+    if schema.is_null() {
+        return Ok(Vec::new());
+    }
+
+    let fields: Vec<ConfigField> = serde_json::from_value(schema)
+        .map_err(|e| format!("Template has an invalid config schema: {}", e))?;
+
+    for (i, field) in fields.iter().enumerate() {
+        if field.id.is_empty() {
+            return Err("Template config schema has a field without an ID".into());
+        }
+
+        if fields[..i].iter().any(|f| f.id == field.id) {
+            return Err(format!(
+                "Template config schema has more than one field with ID {}",
+                field.id
+            )
+            .into());
+        }
+
+        if let ConfigFieldKind::Choice { ref choices } = field.kind {
+            if choices.is_empty() || choices.len() > MAX_CHOICES {
+                return Err(format!(
+                    "Template config field {} must have between 1 and {} choices",
+                    field.id, MAX_CHOICES
+                )
+                .into());
+            }
+        }
+
+        if let Some(ref default) = field.default {
+            field.check(default).map_err(|e| {
+                format!(
+                    "Template config field {} has an invalid default: {}",
+                    field.id, e
+                )
+            })?;
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Validates values against a schema, filling in defaults and dropping unknown keys
+///
+/// Every value is type-checked again so that values kept from an older schema still match the field
+pub fn validate(schema: &[ConfigField], values: &Map<String, Value>) -> Result<Value, String> {
+    let mut config = Map::new();
+
+    for field in schema {
+        match values.get(&field.id).or(field.default.as_ref()) {
+            Some(value) => {
+                config.insert(field.id.clone(), field.check(value)?);
+            }
+            None if field.required => {
+                return Err(format!("{} is required", field.label));
+            }
+            None => {}
+        }
+    }
+
+    Ok(Value::Object(config))
+}
+
+/// The file in a template's content which holds its configuration
+///
+/// Templates read their configuration with `require("./config")` from `init.luau`
+pub const CONFIG_MODULE: &str = "config.luau";
+
+/// Formats a JSON value as a Luau expression
+fn luau_value(value: &Value) -> String {
+    match value {
+        Value::Null => "nil".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => {
+            let mut out = String::with_capacity(s.len() + 2);
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        Value::Array(values) => format!(
+            "{{{}}}",
+            values.iter().map(luau_value).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Object(map) => format!(
+            "{{{}}}",
+            map.iter()
+                .map(|(k, v)| format!(
+                    "[{}] = {}",
+                    luau_value(&Value::String(k.clone())),
+                    luau_value(v)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Creates the Luau module returning a template's configuration as a table
+pub fn config_module(config: &Value) -> String {
+    let mut module = "-- Generated by AntiRaid from the configuration set when the template was loaded\nreturn {\n".to_string();
+
+    if let Some(config) = config.as_object() {
+        for (key, value) in config {
+            module.push_str(&format!(
+                "\t[{}] = {},\n",
+                luau_value(&Value::String(key.clone())),
+                luau_value(value)
+            ));
+        }
+    }
+
+    module.push_str("}\n");
+    module
+}
+
+/// Adds the configuration module to the files of a template, replacing any existing one
+///
+/// Templates are loaded from their files, so this is how the configuration reaches them
+pub fn with_config(content: Value, config: &Value) -> Result<Value, crate::Error> {
+    let Value::Object(mut files) = content else {
+        return Err("Template content must be a map of file names to their source".into());
+    };
+
+    files.insert(
+        CONFIG_MODULE.to_string(),
+        Value::String(config_module(config)),
+    );
+
+    Ok(Value::Object(files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_module() {
+        let module = config_module(&json!({
+            "log_channel": "1234",
+            "threshold": 5,
+            "ratio": 0.5,
+            "enabled": true,
+        }));
+
+        assert!(module.starts_with("-- Generated by AntiRaid"));
+        assert!(module.contains("\t[\"log_channel\"] = \"1234\",\n"));
+        assert!(module.contains("\t[\"threshold\"] = 5,\n"));
+        assert!(module.contains("\t[\"ratio\"] = 0.5,\n"));
+        assert!(module.contains("\t[\"enabled\"] = true,\n"));
+        assert!(module.ends_with("}\n"));
+
+        assert_eq!(
+            config_module(&json!({})),
+            "-- Generated by AntiRaid from the configuration set when the template was loaded\nreturn {\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_config_module_escapes_strings() {
+        let module = config_module(&json!({ "a\"]": "line\n\"quoted\" \\ \u{7}" }));
+
+        assert!(module.contains("\t[\"a\\\"]\"] = \"line\\n\\\"quoted\\\" \\\\ \\u{7}\",\n"));
+    }
+
+    #[test]
+    fn test_with_config() {
+        let content = with_config(
+            json!({ "init.luau": "return 1", "config.luau": "old" }),
+            &json!({ "threshold": 5 }),
+        )
+        .unwrap();
+
+        assert_eq!(content["init.luau"], "return 1");
+        assert!(content[CONFIG_MODULE]
+            .as_str()
+            .unwrap()
+            .contains("[\"threshold\"] = 5"));
+
+        assert!(with_config(Value::Null, &json!({})).is_err());
+    }
+}
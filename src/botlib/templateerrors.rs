@@ -0,0 +1,316 @@
+use super::text::truncate;
+use chrono::{DateTime, Utc};
+use serenity::all::{Embed, GuildId, Message, UserId};
+use sqlx::PgPool;
+
+/// Longest error message stored, longer errors are truncated
+const MAX_ERROR_LENGTH: usize = 4000;
+
+/// Longest event type stored, longer event types are truncated
+const MAX_EVENT_TYPE_LENGTH: usize = 100;
+
+/// Title of the error rate alerts AntiRaid posts to error channels, which are not template errors themselves
+pub const ALERT_TITLE: &str = "Template Error Rate Alert";
+
+#[derive(sqlx::FromRow)]
+pub struct TemplateError {
+    pub event_type: String,
+    pub error: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Error counts of a template
+pub struct ErrorCounts {
+    pub total: i64,
+    pub last_day: i64,
+    /// Errors per event type, most frequent first
+    pub by_event: Vec<(String, i64)>,
+}
+
+/// Stores an error raised by a template
+pub async fn record(
+    pool: &PgPool,
+    guild_id: GuildId,
+    template_name: &str,
+    event_type: &str,
+    error: &str,
+) -> Result<(), crate::Error> {
+    let mut error = error.to_string();
+    error = truncate(&error, MAX_ERROR_LENGTH);
+
+    sqlx::query(
+        "INSERT INTO templates__errors (guild_id, template_name, event_type, error) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(guild_id.to_string())
+    .bind(template_name)
+    .bind(event_type)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// A template error read from a message posted to a template's error channel
+#[derive(Debug, PartialEq)]
+pub struct ParsedError {
+    pub template_name: String,
+    pub event_type: String,
+    pub error: String,
+}
+
+/// Reads a template error from a message the template worker posted to an error channel
+///
+/// `templates` are the templates of the guild using the channel as their error channel. The template is the one
+/// whose name appears in the message, or the only template using the channel. The event type and error are read
+/// from embed fields named `Event` and `Error`, falling back to the embed description or message content.
+/// Messages which do not mention an error at all, such as logs a template sends to the same channel, are ignored
+pub fn parse_error_message(
+    content: &str,
+    embeds: &[Embed],
+    templates: &[String],
+) -> Option<ParsedError> {
+    if embeds
+        .iter()
+        .any(|e| e.title.as_deref() == Some(ALERT_TITLE))
+    {
+        return None;
+    }
+
+    let mut parts = vec![content];
+    for embed in embeds {
+        parts.extend(embed.title.as_deref());
+        parts.extend(embed.description.as_deref());
+        for field in embed.fields.iter() {
+            parts.push(&field.name);
+            parts.push(&field.value);
+        }
+    }
+
+    if !parts.iter().any(|p| p.to_lowercase().contains("error")) {
+        return None;
+    }
+
+    // Longest names first so that `spam` does not match a message from `spam-filter`
+    let mut by_length = templates.iter().collect::<Vec<_>>();
+    by_length.sort_by_key(|t| std::cmp::Reverse(t.len()));
+
+    let template_name = match by_length
+        .into_iter()
+        .find(|t| parts.iter().any(|p| p.contains(t.as_str())))
+    {
+        Some(t) => t.clone(),
+        None if templates.len() == 1 => templates[0].clone(),
+        None => return None,
+    };
+
+    let field = |names: &[&str]| {
+        embeds
+            .iter()
+            .flat_map(|e| e.fields.iter())
+            .find(|f| names.contains(&f.name.trim().to_lowercase().as_str()))
+            .map(|f| f.value.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let event_type = field(&["event", "event type"]).unwrap_or_else(|| "unknown".to_string());
+
+    let error = field(&["error"])
+        .or_else(|| {
+            embeds
+                .iter()
+                .find_map(|e| e.description.as_deref())
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty())
+        })
+        .or_else(|| Some(content.trim().to_string()).filter(|c| !c.is_empty()))?;
+
+    Some(ParsedError {
+        template_name,
+        event_type: truncate(&event_type, MAX_EVENT_TYPE_LENGTH),
+        error,
+    })
+}
+
+/// Records the template error in a message, if it is one
+///
+/// Templates post their errors to their error channel through the template worker, which uses the bot's own
+/// account, so only messages sent by the bot in a channel some template uses as its error channel are read
+pub async fn record_from_message(
+    pool: &PgPool,
+    bot_id: UserId,
+    message: &Message,
+) -> Result<(), crate::Error> {
+    if message.author.id != bot_id {
+        return Ok(());
+    }
+
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+
+    #[derive(sqlx::FromRow)]
+    struct TemplateRecord {
+        name: String,
+    }
+
+    let templates: Vec<TemplateRecord> = sqlx::query_as(
+        "SELECT name FROM guild_templates WHERE guild_id = $1 AND error_channel = $2",
+    )
+    .bind(guild_id.to_string())
+    .bind(message.channel_id.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    if templates.is_empty() {
+        return Ok(());
+    }
+
+    let templates = templates.into_iter().map(|t| t.name).collect::<Vec<_>>();
+
+    let Some(parsed) = parse_error_message(&message.content, &message.embeds, &templates) else {
+        return Ok(());
+    };
+
+    record(
+        pool,
+        guild_id,
+        &parsed.template_name,
+        &parsed.event_type,
+        &parsed.error,
+    )
+    .await
+}
+
+/// Returns a page of the errors of a template, newest first
+pub async fn list(
+    pool: &PgPool,
+    guild_id: GuildId,
+    template_name: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<TemplateError>, crate::Error> {
+    Ok(sqlx::query_as(
+        "SELECT event_type, error, created_at FROM templates__errors WHERE guild_id = $1 AND template_name = $2 ORDER BY created_at DESC LIMIT $3 OFFSET $4",
+    )
+    .bind(guild_id.to_string())
+    .bind(template_name)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn counts(
+    pool: &PgPool,
+    guild_id: GuildId,
+    template_name: &str,
+) -> Result<ErrorCounts, crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct CountRecord {
+        event_type: String,
+        total: i64,
+        last_day: i64,
+    }
+
+    let records: Vec<CountRecord> = sqlx::query_as(
+        "SELECT event_type, COUNT(*) AS total, COUNT(*) FILTER (WHERE created_at > NOW() - INTERVAL '1 day') AS last_day FROM templates__errors WHERE guild_id = $1 AND template_name = $2 GROUP BY event_type ORDER BY total DESC",
+    )
+    .bind(guild_id.to_string())
+    .bind(template_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ErrorCounts {
+        total: records.iter().map(|r| r.total).sum(),
+        last_day: records.iter().map(|r| r.last_day).sum(),
+        by_event: records
+            .into_iter()
+            .map(|r| (r.event_type, r.total))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn embed(value: serde_json::Value) -> Embed {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn templates(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let embeds = [embed(json!({
+            "title": "Error executing template",
+            "description": "Something went wrong",
+            "fields": [
+                { "name": "Template", "value": "spam-filter", "inline": false },
+                { "name": "Event", "value": "MessageCreate", "inline": false },
+                { "name": "Error", "value": "attempt to index nil", "inline": false },
+            ]
+        }))];
+
+        assert_eq!(
+            parse_error_message("", &embeds, &templates(&["spam", "spam-filter"])),
+            Some(ParsedError {
+                template_name: "spam-filter".to_string(),
+                event_type: "MessageCreate".to_string(),
+                error: "attempt to index nil".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_single_template_fallbacks() {
+        let embeds = [embed(json!({
+            "title": "Template Error",
+            "description": "attempt to index nil",
+        }))];
+
+        assert_eq!(
+            parse_error_message("", &embeds, &templates(&["antinuke"])),
+            Some(ParsedError {
+                template_name: "antinuke".to_string(),
+                event_type: "unknown".to_string(),
+                error: "attempt to index nil".to_string(),
+            })
+        );
+
+        assert_eq!(
+            parse_error_message("Runtime error: oops", &[], &templates(&["antinuke"]))
+                .map(|e| e.error),
+            Some("Runtime error: oops".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_other_messages() {
+        // Not an error, e.g. a log message from a template sharing the channel
+        assert_eq!(
+            parse_error_message("User was kicked", &[], &templates(&["antinuke"])),
+            None
+        );
+
+        // Our own error rate alerts
+        let alert = [embed(json!({
+            "title": ALERT_TITLE,
+            "description": "`antinuke` raised **5** errors in the last 10 minutes.",
+        }))];
+        assert_eq!(
+            parse_error_message("", &alert, &templates(&["antinuke"])),
+            None
+        );
+
+        // Several templates use the channel and none is named
+        assert_eq!(
+            parse_error_message("An error occurred", &[], &templates(&["a1", "b1"])),
+            None
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+/// The current version of the template export format
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// The largest template export which can be imported, in bytes
+pub const MAX_EXPORT_SIZE: usize = 1024 * 1024;
+
+/// The prefix of the names shop templates are installed under
+fn shop_template_prefix() -> String {
+    let name = silverpelt::templates::create_shop_template("\0", "");
+    name.split('\0').next().unwrap_or_default().to_string()
+}
+
+/// A guild template exported to a file
+///
+/// Per-guild settings such as the error channel and template configuration are not exported
+#[derive(Serialize, Deserialize)]
+pub struct TemplateExport {
+    pub format_version: u32,
+    pub name: String,
+    pub content: serde_json::Value,
+    pub events: Vec<String>,
+    pub allowed_caps: Vec<String>,
+}
+
+impl TemplateExport {
+    /// Parses and validates an exported template file
+    pub fn from_file(contents: &[u8]) -> Result<Self, crate::Error> {
+        if contents.len() > MAX_EXPORT_SIZE {
+            return Err(format!(
+                "Template file is too large (max {} KB)",
+                MAX_EXPORT_SIZE / 1024
+            )
+            .into());
+        }
+
+        let export: Self = serde_json::from_slice(contents)
+            .map_err(|e| format!("Not a valid template export: {}", e))?;
+
+        if export.format_version != EXPORT_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported template export version {} (expected {})",
+                export.format_version, EXPORT_FORMAT_VERSION
+            )
+            .into());
+        }
+
+        export.validate()?;
+
+        Ok(export)
+    }
+
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            return Err("Template name must be between 1 and 100 characters".into());
+        }
+
+        let prefix = shop_template_prefix();
+        if !prefix.is_empty() && self.name.starts_with(&prefix) {
+            return Err(
+                "That name is reserved for shop templates. Import it under a different name".into(),
+            );
+        }
+
+        // Same layout as `guild_templates.content`, a map of file names to their source with `init.luau` as the entrypoint
+        let Some(files) = self.content.as_object() else {
+            return Err("Template content must be a map of file names to their source".into());
+        };
+
+        if files.values().any(|f| !f.is_string()) {
+            return Err("Template content must be a map of file names to their source".into());
+        }
+
+        if !files.contains_key("init.luau") {
+            return Err("Template content has no `init.luau` entrypoint".into());
+        }
+
+        if self
+            .events
+            .iter()
+            .chain(self.allowed_caps.iter())
+            .any(|s| s.trim().is_empty())
+        {
+            return Err("Template events and capabilities cannot be empty".into());
+        }
+
+        Ok(())
+    }
+
+    /// A file name for the export, safe to use as an attachment name
+    pub fn file_name(&self) -> String {
+        let name = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        format!("{}.template.json", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export() -> TemplateExport {
+        TemplateExport {
+            format_version: EXPORT_FORMAT_VERSION,
+            name: "anti-spam".to_string(),
+            content: json!({ "init.luau": "return {}", "utils.luau": "return 1" }),
+            events: vec!["MessageCreate".to_string()],
+            allowed_caps: vec!["discord:create_message".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_validate_ok() {
+        assert!(export().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        let mut e = export();
+        e.name = " ".to_string();
+        assert!(e.validate().is_err());
+
+        e.name = "a".repeat(101);
+        assert!(e.validate().is_err());
+
+        e.name = silverpelt::templates::create_shop_template("anti-spam", "1.0.0");
+        assert!(e.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_content() {
+        let mut e = export();
+        e.content = json!("return {}");
+        assert!(e.validate().is_err());
+
+        e.content = json!({ "init.luau": 1 });
+        assert!(e.validate().is_err());
+
+        e.content = json!({ "main.luau": "return {}" });
+        assert!(e.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_events_and_caps() {
+        let mut e = export();
+        e.events.push(" ".to_string());
+        assert!(e.validate().is_err());
+
+        let mut e = export();
+        e.allowed_caps.push(String::new());
+        assert!(e.validate().is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let file = serde_json::to_vec(&export()).unwrap();
+        let parsed = TemplateExport::from_file(&file).unwrap();
+        assert_eq!(parsed.name, "anti-spam");
+        assert_eq!(parsed.content, export().content);
+
+        let mut e = export();
+        e.format_version = EXPORT_FORMAT_VERSION + 1;
+        assert!(TemplateExport::from_file(&serde_json::to_vec(&e).unwrap()).is_err());
+
+        assert!(TemplateExport::from_file(b"not json").is_err());
+        assert!(TemplateExport::from_file(&vec![b' '; MAX_EXPORT_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_file_name() {
+        let mut e = export();
+        e.name = "my template/v2".to_string();
+        assert_eq!(e.file_name(), "my_template_v2.template.json");
+    }
+}
@@ -0,0 +1,143 @@
+use semver::{Version, VersionReq};
+
+/// Resolves a requested template version against the versions available in the shop
+///
+/// `latest` picks the highest stable version (or the highest pre-release if there are no stable versions),
+/// an exact version must exist as-is and anything else is treated as a requirement such as `^1.2` or `~2.0`.
+/// Versions which are not valid semver can only be loaded by their exact name
+pub fn resolve_version(available: &[String], requested: &str) -> Result<String, crate::Error> {
+    let requested = requested.trim();
+
+    if requested != "latest" {
+        if let Some(exact) = available.iter().find(|v| v.as_str() == requested) {
+            return Ok(exact.clone());
+        }
+    }
+
+    let mut parsed = available
+        .iter()
+        .filter_map(|v| {
+            Version::parse(v.trim_start_matches('v'))
+                .ok()
+                .map(|p| (p, v))
+        })
+        .collect::<Vec<_>>();
+
+    parsed.sort_by(|a, b| b.0.cmp(&a.0));
+
+    if requested == "latest" {
+        return parsed
+            .iter()
+            .find(|(p, _)| p.pre.is_empty())
+            .or_else(|| parsed.first())
+            .map(|(_, v)| v.to_string())
+            .ok_or_else(|| "No versions of this template found in the shop".into());
+    }
+
+    let req = VersionReq::parse(requested)
+        .map_err(|e| format!("Invalid version or version requirement: {}", e))?;
+
+    parsed
+        .iter()
+        .find(|(p, _)| req.matches(p))
+        .map(|(_, v)| v.to_string())
+        .ok_or_else(|| format!("No version of this template matches `{}`", requested).into())
+}
+
+/// Sorts template versions newest first
+///
+/// Versions which are not valid semver are kept in their original order after all semver versions
+pub fn sort_versions(versions: &mut [String]) {
+    versions.sort_by(|a, b| {
+        match (
+            Version::parse(a.trim_start_matches('v')),
+            Version::parse(b.trim_start_matches('v')),
+        ) {
+            (Ok(a), Ok(b)) => b.cmp(&a),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_latest_compares_numerically() {
+        let available = versions(&["1.9.0", "1.10.0", "1.2.0"]);
+        assert_eq!(resolve_version(&available, "latest").unwrap(), "1.10.0");
+    }
+
+    #[test]
+    fn test_latest_prefers_stable() {
+        let available = versions(&["1.0.0", "2.0.0-beta.1", "1.1.0"]);
+        assert_eq!(resolve_version(&available, "latest").unwrap(), "1.1.0");
+
+        // Only pre-releases, so the newest pre-release is picked
+        let available = versions(&["2.0.0-alpha", "2.0.0-beta.2", "2.0.0-beta.10"]);
+        assert_eq!(
+            resolve_version(&available, "latest").unwrap(),
+            "2.0.0-beta.10"
+        );
+    }
+
+    #[test]
+    fn test_latest_ignores_non_semver() {
+        let available = versions(&["old", "1.0.0"]);
+        assert_eq!(resolve_version(&available, "latest").unwrap(), "1.0.0");
+
+        assert!(resolve_version(&versions(&["old"]), "latest").is_err());
+        assert!(resolve_version(&[], "latest").is_err());
+    }
+
+    #[test]
+    fn test_exact_and_requirements() {
+        let available = versions(&["1.2.0", "1.3.1", "2.0.0", "2.1.0-rc.1", "old", "v3.0.0"]);
+
+        assert_eq!(resolve_version(&available, "1.2.0").unwrap(), "1.2.0");
+        assert_eq!(resolve_version(&available, "old").unwrap(), "old");
+        assert_eq!(resolve_version(&available, "^1.2").unwrap(), "1.3.1");
+        assert_eq!(resolve_version(&available, "~2.0").unwrap(), "2.0.0");
+        assert_eq!(resolve_version(&available, "^3").unwrap(), "v3.0.0");
+
+        // Requirements without a pre-release never match pre-releases
+        assert_eq!(resolve_version(&available, ">=2.0.0").unwrap(), "v3.0.0");
+        assert!(resolve_version(&available, "~2.1").is_err());
+
+        assert!(resolve_version(&available, "^4").is_err());
+        assert!(resolve_version(&available, "not a version").is_err());
+    }
+
+    #[test]
+    fn test_sort_versions() {
+        let mut available = versions(&[
+            "1.9.0",
+            "legacy",
+            "2.0.0-beta.1",
+            "1.10.0",
+            "2.0.0",
+            "2.0.0-alpha",
+        ]);
+
+        sort_versions(&mut available);
+
+        assert_eq!(
+            available,
+            versions(&[
+                "2.0.0",
+                "2.0.0-beta.1",
+                "2.0.0-alpha",
+                "1.10.0",
+                "1.9.0",
+                "legacy"
+            ])
+        );
+    }
+}
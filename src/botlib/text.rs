@@ -0,0 +1,40 @@
+/// Truncates a string to at most `max_len` bytes, ending it with `...` if anything was cut
+///
+/// Cuts on a character boundary so that multi-byte characters are never split
+pub fn truncate(value: &str, max_len: usize) -> String {
+    if value.len() <= max_len {
+        return value.to_string();
+    }
+
+    let mut end = max_len.saturating_sub(3);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}...", &value[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_short() {
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_long() {
+        assert_eq!(truncate("hello world", 8), "hello...");
+        assert!(truncate(&"a".repeat(5000), 4000).len() <= 4000);
+    }
+
+    #[test]
+    fn test_truncate_char_boundary() {
+        // Each é is 2 bytes, so a cut at byte 6 would split one
+        let truncated = truncate("éééééé", 9);
+        assert_eq!(truncated, "ééé...");
+        assert!(truncated.len() <= 9);
+    }
+}
@@ -0,0 +1,326 @@
+use lockdowns::{
+    CreateLockdownMode, LockdownDataStore, LockdownMode, LockdownModeHandle, LockdownModeHandles,
+    LockdownSharableData,
+};
+use serenity::all::{
+    ChannelId, ChannelType, GuildChannel, PartialGuild, PermissionOverwrite,
+    PermissionOverwriteType, Permissions, RoleId,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Permissions denied to critical roles in locked voice/stage channels
+pub const VOICE_LOCKDOWN_PERMISSIONS: Permissions = Permissions::CONNECT.union(Permissions::SPEAK);
+
+/// Voice channel lockdown
+///
+/// Denies CONNECT and SPEAK to critical roles in either all voice and stage channels or a single one
+pub struct VoiceChannelLockdown(pub Option<ChannelId>);
+
+impl VoiceChannelLockdown {
+    /// Returns whether the given channel is affected by this lockdown
+    fn affects(&self, channel: &GuildChannel) -> bool {
+        if !matches!(channel.kind, ChannelType::Voice | ChannelType::Stage) {
+            return false;
+        }
+
+        match self.0 {
+            Some(channel_id) => channel.id == channel_id,
+            None => true,
+        }
+    }
+}
+
+pub struct CreateVoiceChannelLockdown;
+
+/// Puts back the voice permissions of critical roles as they were before the lockdown
+///
+/// Only the `VOICE_LOCKDOWN_PERMISSIONS` bits of critical role overwrites are touched, so changes made by
+/// other lockdowns or moderators since the lockdown was created are kept. An overwrite the lockdown added
+/// is removed again once it no longer allows or denies anything
+fn restore_voice_permissions(
+    current: &[PermissionOverwrite],
+    old: &[PermissionOverwrite],
+    critical_roles: &HashSet<RoleId>,
+) -> Vec<PermissionOverwrite> {
+    let mut overwrites = Vec::with_capacity(current.len());
+
+    for ow in current {
+        let PermissionOverwriteType::Role(role_id) = ow.kind else {
+            overwrites.push(ow.clone());
+            continue;
+        };
+
+        if !critical_roles.contains(&role_id) {
+            overwrites.push(ow.clone());
+            continue;
+        }
+
+        let old_ow = old.iter().find(|o| o.kind == ow.kind);
+
+        let mut restored = ow.clone();
+        restored.allow.remove(VOICE_LOCKDOWN_PERMISSIONS);
+        restored.deny.remove(VOICE_LOCKDOWN_PERMISSIONS);
+
+        if let Some(old_ow) = old_ow {
+            restored
+                .allow
+                .insert(old_ow.allow & VOICE_LOCKDOWN_PERMISSIONS);
+            restored
+                .deny
+                .insert(old_ow.deny & VOICE_LOCKDOWN_PERMISSIONS);
+        } else if restored.allow.is_empty() && restored.deny.is_empty() {
+            continue;
+        }
+
+        overwrites.push(restored);
+    }
+
+    overwrites
+}
+
+/// Registers the voice channel lockdown so that stored `vcl` lockdowns can be loaded into a `LockdownSet`
+///
+/// Must be called once at startup before any `LockdownSet` is loaded, whichever command is being run
+pub fn register() {
+    lockdowns::CREATE_LOCKDOWN_MODES
+        .insert("vcl".to_string(), Box::new(CreateVoiceChannelLockdown));
+}
+
+impl CreateLockdownMode for CreateVoiceChannelLockdown {
+    fn syntax(&self) -> &'static str {
+        "vcl or vcl/<channel_id>"
+    }
+
+    fn to_lockdown_mode(&self, s: &str) -> Result<Option<Box<dyn LockdownMode>>, lockdowns::Error> {
+        if s == "vcl" {
+            return Ok(Some(Box::new(VoiceChannelLockdown(None))));
+        }
+
+        match s.strip_prefix("vcl/") {
+            Some(channel_id) => Ok(Some(Box::new(VoiceChannelLockdown(Some(
+                channel_id.parse()?,
+            ))))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LockdownMode for VoiceChannelLockdown {
+    fn creator(&self) -> Box<dyn CreateLockdownMode> {
+        Box::new(CreateVoiceChannelLockdown)
+    }
+
+    fn string_form(&self) -> String {
+        match self.0 {
+            Some(channel_id) => format!("vcl/{}", channel_id),
+            None => "vcl".to_string(),
+        }
+    }
+
+    // A single voice channel is as specific as a SCL, all voice channels as specific as a TSL
+    fn specificity(&self) -> usize {
+        match self.0 {
+            Some(_) => 2,
+            None => 1,
+        }
+    }
+
+    async fn setup(
+        &self,
+        _lockdown_data: &dyn LockdownDataStore,
+        _pg: &PartialGuild,
+        pgc: &[GuildChannel],
+        _critical_roles: &HashSet<RoleId>,
+    ) -> Result<serde_json::Value, lockdowns::Error> {
+        let mut map = HashMap::new();
+
+        for channel in pgc.iter().filter(|c| self.affects(c)) {
+            map.insert(
+                channel.id,
+                channel
+                    .permission_overwrites
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        if map.is_empty() {
+            return Err("No voice or stage channels to lock down".into());
+        }
+
+        Ok(serde_json::to_value(map)?)
+    }
+
+    async fn shareable(
+        &self,
+        data: &serde_json::Value,
+    ) -> Result<LockdownSharableData, lockdowns::Error> {
+        let data: HashMap<ChannelId, Vec<PermissionOverwrite>> =
+            serde_json::from_value(data.clone())?;
+
+        Ok(LockdownSharableData {
+            role_permissions: HashMap::new(),
+            channel_permissions: data,
+        })
+    }
+
+    async fn create(
+        &self,
+        lockdown_data: &dyn LockdownDataStore,
+        _pg: &mut PartialGuild,
+        pgc: &mut [GuildChannel],
+        critical_roles: &HashSet<RoleId>,
+        _data: &serde_json::Value,
+        _all_handles: &LockdownModeHandles,
+    ) -> Result<(), lockdowns::Error> {
+        for channel in pgc.iter_mut().filter(|c| self.affects(c)) {
+            let mut overwrites = channel.permission_overwrites.to_vec();
+
+            for role_id in critical_roles {
+                let kind = PermissionOverwriteType::Role(*role_id);
+
+                match overwrites.iter_mut().find(|o| o.kind == kind) {
+                    Some(existing) => {
+                        existing.allow.remove(VOICE_LOCKDOWN_PERMISSIONS);
+                        existing.deny.insert(VOICE_LOCKDOWN_PERMISSIONS);
+                    }
+                    None => overwrites.push(PermissionOverwrite {
+                        allow: Permissions::empty(),
+                        deny: VOICE_LOCKDOWN_PERMISSIONS,
+                        kind,
+                    }),
+                }
+            }
+
+            let new_channel = channel
+                .id
+                .edit(
+                    lockdown_data.http(),
+                    serenity::all::EditChannel::new()
+                        .permissions(overwrites)
+                        .audit_log_reason("Voice channel lockdown"),
+                )
+                .await?;
+
+            *channel = new_channel;
+        }
+
+        Ok(())
+    }
+
+    async fn revert(
+        &self,
+        lockdown_data: &dyn LockdownDataStore,
+        _pg: &mut PartialGuild,
+        pgc: &mut [GuildChannel],
+        critical_roles: &HashSet<RoleId>,
+        data: &serde_json::Value,
+        _all_handles: &LockdownModeHandles,
+    ) -> Result<(), lockdowns::Error> {
+        let old_permissions: HashMap<ChannelId, Vec<PermissionOverwrite>> =
+            serde_json::from_value(data.clone())?;
+
+        for channel in pgc.iter_mut() {
+            let Some(old_overwrites) = old_permissions.get(&channel.id) else {
+                continue;
+            };
+
+            let overwrites = restore_voice_permissions(
+                &channel.permission_overwrites,
+                old_overwrites,
+                critical_roles,
+            );
+
+            if overwrites.as_slice() == &*channel.permission_overwrites {
+                continue;
+            }
+
+            let new_channel = channel
+                .id
+                .edit(
+                    lockdown_data.http(),
+                    serenity::all::EditChannel::new()
+                        .permissions(overwrites)
+                        .audit_log_reason("Voice channel lockdown removed"),
+                )
+                .await?;
+
+            *channel = new_channel;
+        }
+
+        Ok(())
+    }
+
+    fn handles(
+        &self,
+        _pg: &PartialGuild,
+        pgc: &[GuildChannel],
+        _critical_roles: &HashSet<RoleId>,
+        _data: &serde_json::Value,
+        _all_handles: &LockdownModeHandles,
+    ) -> Result<LockdownModeHandle, lockdowns::Error> {
+        Ok(LockdownModeHandle {
+            roles: HashSet::new(),
+            channels: pgc
+                .iter()
+                .filter(|c| self.affects(c))
+                .map(|c| c.id)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role_overwrite(role_id: u64, allow: Permissions, deny: Permissions) -> PermissionOverwrite {
+        PermissionOverwrite {
+            allow,
+            deny,
+            kind: PermissionOverwriteType::Role(RoleId::new(role_id)),
+        }
+    }
+
+    #[test]
+    fn test_restore_removes_added_overwrite() {
+        let critical_roles = HashSet::from([RoleId::new(1)]);
+        let current = vec![role_overwrite(
+            1,
+            Permissions::empty(),
+            VOICE_LOCKDOWN_PERMISSIONS,
+        )];
+
+        assert!(restore_voice_permissions(&current, &[], &critical_roles).is_empty());
+    }
+
+    #[test]
+    fn test_restore_keeps_other_changes() {
+        let critical_roles = HashSet::from([RoleId::new(1)]);
+        let old = vec![role_overwrite(
+            1,
+            Permissions::CONNECT,
+            Permissions::empty(),
+        )];
+        // Another lockdown denied SEND_MESSAGES after the voice lockdown was created
+        let current = vec![
+            role_overwrite(
+                1,
+                Permissions::empty(),
+                VOICE_LOCKDOWN_PERMISSIONS | Permissions::SEND_MESSAGES,
+            ),
+            role_overwrite(2, Permissions::empty(), Permissions::SPEAK),
+        ];
+
+        let restored = restore_voice_permissions(&current, &old, &critical_roles);
+        assert_eq!(
+            restored,
+            vec![
+                role_overwrite(1, Permissions::CONNECT, Permissions::SEND_MESSAGES),
+                role_overwrite(2, Permissions::empty(), Permissions::SPEAK),
+            ]
+        );
+    }
+}
@@ -28,6 +28,7 @@ pub static CONNECT_STATE: LazyLock<ConnectState> = LazyLock::new(|| ConnectState
 });
 
 static START_RPC: Once = Once::new();
+static START_TASKS: Once = Once::new();
 
 async fn event_listener(
     ctx: poise::FrameworkContext<'_, Data, Error>,
@@ -76,10 +77,50 @@ async fn event_listener(
                 });
             });
 
+            // Background tasks work on all guilds, so only the cluster running shard 0 starts them
+            if ctx.serenity_context.shard_id.0 == 0 {
+                START_TASKS.call_once(|| {
+                    info!("Starting background tasks");
+                    let serenity_context = ctx.serenity_context.clone();
+
+                    tokio::task::spawn(crate::tasks::lockdown_verifier::lockdown_verifier(
+                        serenity_context.clone(),
+                    ));
+                    tokio::task::spawn(crate::tasks::backup_scheduler::backup_scheduler(
+                        serenity_context.clone(),
+                    ));
+                    tokio::task::spawn(crate::tasks::backup_checksums::backup_checksums(
+                        serenity_context.clone(),
+                    ));
+                    tokio::task::spawn(crate::tasks::restore_copies::restore_copies(
+                        serenity_context.clone(),
+                    ));
+                    tokio::task::spawn(crate::tasks::template_error_alerts::template_error_alerts(
+                        serenity_context,
+                    ));
+                });
+            }
+
             CONNECT_STATE
                 .ready
                 .insert(ctx.serenity_context.shard_id, true);
         }
+        FullEvent::Message { new_message } => {
+            // Template errors are posted by the template worker as the bot, so these are cheap to skip
+            let bot_id = ctx.serenity_context.cache.current_user().id;
+            if new_message.author.id != bot_id {
+                return Ok(());
+            }
+
+            let data = ctx.serenity_context.data::<Data>();
+
+            if let Err(e) =
+                crate::botlib::templateerrors::record_from_message(&data.pool, bot_id, new_message)
+                    .await
+            {
+                error!("Failed to record template error: {}", e);
+            }
+        }
         _ => {}
     }
 
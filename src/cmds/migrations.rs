@@ -313,4 +313,213 @@ pub async fn start() {
             .await
             .expect("Could not update job");
     }
+
+    //* Migration #4 - Lockdown verification state
+    println!("lockdown__guild_snapshots, lockdown__autoverify: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS lockdown__guild_snapshots (
+            guild_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            snapshot JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (guild_id, kind)
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create lockdown__guild_snapshots");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS lockdown__autoverify (
+            guild_id TEXT PRIMARY KEY,
+            alert_channel TEXT NOT NULL,
+            auto_repair BOOLEAN NOT NULL DEFAULT FALSE,
+            last_report TEXT
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create lockdown__autoverify");
+
+    //* Migration #5 - Panic mode state
+    println!("antiraid__panic_states: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS antiraid__panic_states (
+            guild_id TEXT PRIMARY KEY,
+            lockdown_id UUID,
+            previous_verification_level INTEGER NOT NULL,
+            previous_invites_disabled BOOLEAN NOT NULL,
+            timed_out_users TEXT[] NOT NULL DEFAULT '{}',
+            created_by TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create antiraid__panic_states");
+
+    //* Migration #6 - Scheduled backups
+    println!("backups__schedules: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS backups__schedules (
+            guild_id TEXT PRIMARY KEY,
+            interval_secs BIGINT NOT NULL,
+            options JSONB NOT NULL,
+            keep_last INTEGER NOT NULL,
+            keep_weekly INTEGER NOT NULL,
+            next_run_at TIMESTAMPTZ NOT NULL,
+            last_run_at TIMESTAMPTZ,
+            created_by TEXT NOT NULL
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create backups__schedules");
+
+    println!("backups__job_metadata: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS backups__job_metadata (
+            job_id UUID PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            scheduled BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create backups__job_metadata");
+
+    //* Migration #7 - Backup labels, notes and pinning
+    println!("backups__job_metadata: add label, note and pinned");
+
+    sqlx::query(
+        "ALTER TABLE backups__job_metadata
+            ADD COLUMN IF NOT EXISTS label TEXT,
+            ADD COLUMN IF NOT EXISTS note TEXT,
+            ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT FALSE",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not add label, note and pinned to backups__job_metadata");
+
+    //* Migration #8 - Backup checksums
+    println!("backups__job_metadata: add checksum");
+
+    sqlx::query("ALTER TABLE backups__job_metadata ADD COLUMN IF NOT EXISTS checksum TEXT")
+        .execute(&pg_pool)
+        .await
+        .expect("Could not add checksum to backups__job_metadata");
+
+    //* Migration #9 - Backup storage quotas
+    println!("backups__job_metadata: add size_bytes");
+
+    sqlx::query("ALTER TABLE backups__job_metadata ADD COLUMN IF NOT EXISTS size_bytes BIGINT")
+        .execute(&pg_pool)
+        .await
+        .expect("Could not add size_bytes to backups__job_metadata");
+
+    println!("backups__quotas: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS backups__quotas (
+            guild_id TEXT PRIMARY KEY,
+            quota_mb BIGINT NOT NULL
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create backups__quotas");
+
+    //* Migration #10 - Template configuration
+    println!("template_shop: add config_schema");
+
+    sqlx::query(
+        "ALTER TABLE template_shop ADD COLUMN IF NOT EXISTS config_schema JSONB NOT NULL DEFAULT '[]'",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not add config_schema to template_shop");
+
+    println!("guild_templates: add config");
+
+    sqlx::query(
+        "ALTER TABLE guild_templates ADD COLUMN IF NOT EXISTS config JSONB NOT NULL DEFAULT '{}'",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not add config to guild_templates");
+
+    //* Migration #11 - Template error log
+    println!("templates__errors: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS templates__errors (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            guild_id TEXT NOT NULL,
+            template_name TEXT NOT NULL,
+            event_type TEXT NOT NULL,
+            error TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create templates__errors");
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS templates__errors_template_idx ON templates__errors (guild_id, template_name, created_at DESC)",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create templates__errors_template_idx");
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS templates__errors_created_at_idx ON templates__errors (created_at)",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create templates__errors_created_at_idx");
+
+    println!("templates__error_alerts: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS templates__error_alerts (
+            guild_id TEXT NOT NULL,
+            template_name TEXT NOT NULL,
+            last_alert_at TIMESTAMPTZ NOT NULL,
+            PRIMARY KEY (guild_id, template_name)
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create templates__error_alerts");
+
+    //* Migration #12 - AntiRaid configuration in backups
+    println!("backups__job_metadata: add has_antiraid_config");
+
+    sqlx::query(
+        "ALTER TABLE backups__job_metadata ADD COLUMN IF NOT EXISTS has_antiraid_config BOOLEAN NOT NULL DEFAULT FALSE",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not add has_antiraid_config to backups__job_metadata");
+
+    //* Migration #13 - Cleanup of backups copied from another server
+    println!("backups__restore_copies: create");
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS backups__restore_copies (
+            restore_job_id UUID PRIMARY KEY,
+            guild_id TEXT NOT NULL,
+            path TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )",
+    )
+    .execute(&pg_pool)
+    .await
+    .expect("Could not create backups__restore_copies");
 }
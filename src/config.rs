@@ -115,6 +115,44 @@ pub struct BasePorts {
     pub template_worker_port: u16,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct Backups {
+    /// Default storage quota for backups of a guild, in megabytes
+    pub default_quota_mb: u64,
+    /// Whether the jobserver supports the RemapIds restore option
+    #[serde(default)]
+    pub remap_ids: bool,
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Self {
+            default_quota_mb: 1024,
+            remap_ids: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TemplateErrors {
+    /// Number of errors within the alert window after which a template's error channel is alerted
+    pub alert_threshold: u32,
+    /// Length of the window errors are counted in, in minutes. At most one alert is sent per window
+    pub alert_window_mins: u32,
+    /// Number of days template errors are kept for
+    pub retention_days: u32,
+}
+
+impl Default for TemplateErrors {
+    fn default() -> Self {
+        Self {
+            alert_threshold: 10,
+            alert_window_mins: 10,
+            retention_days: 30,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub discord_auth: DiscordAuth,
@@ -123,6 +161,10 @@ pub struct Config {
     pub servers: Servers,
     pub object_storage: ObjectStorage,
     pub base_ports: BasePorts,
+    #[serde(default)]
+    pub backups: Backups,
+    #[serde(default)]
+    pub template_errors: TemplateErrors,
 
     #[serde(skip)]
     /// Setup by load() for statistics
@@ -4,10 +4,14 @@ mod botlib;
 mod cmds;
 mod config;
 mod rpc;
+mod tasks;
 
 pub use botlib::{Command, Context, Error};
 
 #[tokio::main]
 async fn main() {
+    // Register lockdown modes implemented by the bot itself, the bot, rpc server and tasks all load lockdowns
+    botlib::vcl::register();
+
     cmds::cmd_loader().await;
 }
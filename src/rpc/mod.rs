@@ -3,11 +3,20 @@ pub mod types;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
+use silverpelt::lockdowns::LockdownData;
 use std::sync::Arc;
 
+use crate::{
+    bot::sandwich_config,
+    botlib::{
+        lockdown_snapshots::{self, SnapshotKind},
+        settings,
+    },
+};
+
 type Response<T> = Result<Json<T>, (StatusCode, String)>;
 
 #[derive(Clone)]
@@ -37,7 +46,15 @@ pub fn create_bot_rpc_server(
         .route(
             "/check-user-has-permission/:guild_id/:user_id",
             post(check_user_has_permission),
-        );
+        )
+        // Lists, creates and removes lockdowns on behalf of a user [Lockdown]
+        .route(
+            "/lockdowns/:guild_id/:user_id",
+            get(list_lockdowns).post(create_lockdown),
+        )
+        .route("/lockdowns/:guild_id/:user_id/:id", delete(remove_lockdown))
+        // Runs a view, create, update or delete operation on a setting on behalf of a user [SettingsOperation]
+        .route("/settings/:guild_id/:user_id", post(settings_operation));
     let router: Router<()> = router.with_state(AppData::new(data, ctx));
     router.into_make_service()
 }
@@ -90,3 +107,244 @@ async fn check_user_has_permission(
         },
     }))
 }
+
+/// Checks a kittycat permission on behalf of a user, returning a 403 if the check fails
+async fn check_permission_for(
+    data: &silverpelt::data::Data,
+    serenity_context: &serenity::all::Context,
+    guild_id: serenity::all::GuildId,
+    user_id: serenity::all::UserId,
+    perm: &str,
+) -> Result<(), (StatusCode, String)> {
+    crate::botlib::permission_checks::check_permissions(
+        guild_id,
+        user_id,
+        &data.pool,
+        serenity_context,
+        &data.reqwest,
+        &None,
+        perm.into(),
+    )
+    .await
+    .map_err(|e| (StatusCode::FORBIDDEN, e.to_string()))
+}
+
+/// Lists all active lockdowns of a guild [Lockdown]
+async fn list_lockdowns(
+    State(AppData {
+        data,
+        serenity_context,
+        ..
+    }): State<AppData>,
+    Path((guild_id, user_id)): Path<(serenity::all::GuildId, serenity::all::UserId)>,
+) -> Response<Vec<types::Lockdown>> {
+    check_permission_for(
+        &data,
+        &serenity_context,
+        guild_id,
+        user_id,
+        "lockdowns.list",
+    )
+    .await?;
+
+    let lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            serenity_context.cache.clone(),
+            serenity_context.http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error while fetching lockdown set: {}", e),
+        )
+    })?;
+
+    Ok(Json(
+        lockdowns
+            .lockdowns()
+            .iter()
+            .map(|l| types::Lockdown {
+                id: l.id,
+                lockdown_type: l.r#type.string_form(),
+                reason: l.reason.clone(),
+                created_at: l.created_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Starts a new lockdown [CreateLockdown]
+async fn create_lockdown(
+    State(AppData {
+        data,
+        serenity_context,
+        ..
+    }): State<AppData>,
+    Path((guild_id, user_id)): Path<(serenity::all::GuildId, serenity::all::UserId)>,
+    Json(req): Json<types::CreateLockdownRequest>,
+) -> Response<types::CreateLockdownResponse> {
+    let (lockdown_type, perm) =
+        crate::bot::lockdowns::create_lockdown_mode(&req.lockdown_type, req.target.as_deref())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    check_permission_for(&data, &serenity_context, guild_id, user_id, perm).await?;
+
+    let mut lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            serenity_context.cache.clone(),
+            serenity_context.http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error while fetching lockdown set: {}", e),
+        )
+    })?;
+
+    let existing_ids = lockdowns
+        .lockdowns()
+        .iter()
+        .map(|l| l.id)
+        .collect::<Vec<_>>();
+
+    if existing_ids.is_empty() {
+        lockdown_snapshots::record(
+            &serenity_context.http,
+            &data.pool,
+            guild_id,
+            SnapshotKind::Baseline,
+        )
+        .await;
+    }
+
+    lockdowns
+        .apply(lockdown_type, &req.reason)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error while applying lockdown: {}", e),
+            )
+        })?;
+
+    lockdown_snapshots::record(
+        &serenity_context.http,
+        &data.pool,
+        guild_id,
+        SnapshotKind::Expected,
+    )
+    .await;
+
+    Ok(Json(types::CreateLockdownResponse {
+        id: lockdowns
+            .lockdowns()
+            .iter()
+            .find(|l| !existing_ids.contains(&l.id))
+            .map(|l| l.id),
+    }))
+}
+
+/// Removes a lockdown by ID
+async fn remove_lockdown(
+    State(AppData {
+        data,
+        serenity_context,
+        ..
+    }): State<AppData>,
+    Path((guild_id, user_id, id)): Path<(
+        serenity::all::GuildId,
+        serenity::all::UserId,
+        uuid::Uuid,
+    )>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    check_permission_for(
+        &data,
+        &serenity_context,
+        guild_id,
+        user_id,
+        "lockdowns.remove",
+    )
+    .await?;
+
+    let mut lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            serenity_context.cache.clone(),
+            serenity_context.http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error while fetching lockdown set: {}", e),
+        )
+    })?;
+
+    if !lockdowns.lockdowns().iter().any(|l| l.id == id) {
+        return Err((StatusCode::NOT_FOUND, "Lockdown not found".to_string()));
+    }
+
+    lockdowns.remove(id).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error while removing lockdown: {}", e),
+        )
+    })?;
+
+    lockdown_snapshots::record_after_remove(
+        &serenity_context.http,
+        &data.pool,
+        guild_id,
+        lockdowns.lockdowns().is_empty(),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Runs an operation on a setting on behalf of a user [SettingsOperation]
+async fn settings_operation(
+    State(AppData {
+        data,
+        serenity_context,
+        ..
+    }): State<AppData>,
+    Path((guild_id, user_id)): Path<(serenity::all::GuildId, serenity::all::UserId)>,
+    Json(req): Json<types::SettingsOperationRequest>,
+) -> Response<types::CanonicalSettingsResult> {
+    let fields = settings::run_operation(
+        &data,
+        &serenity_context,
+        guild_id,
+        user_id,
+        &req.setting,
+        req.op,
+        req.fields,
+    )
+    .await
+    .map_err(|e| match e {
+        settings::SettingsOperationError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+        settings::SettingsOperationError::Unsupported(e) => (StatusCode::METHOD_NOT_ALLOWED, e),
+        settings::SettingsOperationError::Forbidden(e) => (StatusCode::FORBIDDEN, e),
+        settings::SettingsOperationError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+        settings::SettingsOperationError::Failed(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+    })?;
+
+    Ok(Json(types::CanonicalSettingsResult::Ok { fields }))
+}
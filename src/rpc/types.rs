@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use serenity::all::{GuildChannel, Permissions, Role, RoleId};
+use serenity::all::{GuildChannel, Permissions, Role, RoleId, UserId};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GuildChannelWithPermissions {
@@ -69,6 +69,31 @@ pub struct CheckUserHasKittycatPermissionsRequest {
     pub perm: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lockdown {
+    pub id: uuid::Uuid,
+    #[serde(rename = "type")]
+    pub lockdown_type: String,
+    pub reason: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateLockdownRequest {
+    /// The type of the lockdown (tsl, qsl, scl, role or vcl)
+    #[serde(rename = "type")]
+    pub lockdown_type: String,
+    pub reason: String,
+    /// The channel/role to lock down for lockdown types which need one
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateLockdownResponse {
+    /// The ID of the newly created lockdown
+    pub id: Option<uuid::Uuid>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct BotState {
     pub commands: Vec<crate::botlib::canonical::CanonicalCommand>,
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use silverpelt::data::Data;
+use sqlx::types::uuid::Uuid;
+use std::time::Duration;
+
+use crate::botlib::backupfile;
+
+/// How often backups without a checksum are checked for completion
+const CHECKSUM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Backups whose job was created longer ago than this are assumed to have failed and are no longer checked
+const CHECKSUM_WINDOW_HOURS: i64 = 24;
+
+/// How long after its job a metadata row may be created and still count as created along with the backup
+const SPAWN_SLACK_MINUTES: i64 = 5;
+
+#[derive(sqlx::FromRow)]
+struct PendingRecord {
+    job_id: Uuid,
+    created_at: DateTime<Utc>,
+}
+
+/// Records the checksum and size of backups once their job completes
+///
+/// Metadata rows are also created for existing backups (e.g. when pinning one or computing storage usage). Only rows
+/// created along with the job when the backup was spawned get a checksum, as a file that has sat in storage may
+/// already be corrupt and is never trusted as the reference. The checksum of any other row stays NULL
+pub async fn backup_checksums(ctx: serenity::all::Context) {
+    let mut interval = tokio::time::interval(CHECKSUM_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let data = ctx.data::<Data>();
+
+        let pending: Vec<PendingRecord> = match sqlx::query_as(
+            "SELECT job_id, created_at FROM backups__job_metadata WHERE checksum IS NULL AND created_at > NOW() - INTERVAL '1 day'",
+        )
+        .fetch_all(&data.pool)
+        .await
+        {
+            Ok(pending) => pending,
+            Err(e) => {
+                log::error!("Failed to fetch backups without a checksum: {}", e);
+                continue;
+            }
+        };
+
+        for rec in pending {
+            if let Err(e) = record_if_completed(&data, &rec).await {
+                log::error!("Failed to record checksum of backup {}: {}", rec.job_id, e);
+            }
+        }
+    }
+}
+
+async fn record_if_completed(data: &Data, rec: &PendingRecord) -> Result<(), crate::Error> {
+    let job = jobserver::Job::from_id(rec.job_id, &data.pool).await?;
+
+    if job.name != "guild_create_backup" || job.state != "completed" {
+        return Ok(());
+    }
+
+    if job.created_at < Utc::now() - chrono::Duration::hours(CHECKSUM_WINDOW_HOURS)
+        || rec.created_at > job.created_at + chrono::Duration::minutes(SPAWN_SLACK_MINUTES)
+    {
+        return Ok(());
+    }
+
+    backupfile::record_checksum(data, &job).await
+}
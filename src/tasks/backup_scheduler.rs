@@ -0,0 +1,270 @@
+use chrono::{DateTime, Utc};
+use serenity::all::GuildId;
+use silverpelt::data::Data;
+use sqlx::types::uuid::Uuid;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::botlib::{backupfile, backupusage};
+use crate::config::CONFIG;
+
+/// How often the backup schedules are checked for due backups
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(sqlx::FromRow)]
+struct ScheduleRecord {
+    guild_id: String,
+    options: serde_json::Value,
+    keep_last: i32,
+    keep_weekly: i32,
+}
+
+/// Creates scheduled backups once they are due and prunes old scheduled backups
+pub async fn backup_scheduler(ctx: serenity::all::Context) {
+    let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let data = ctx.data::<Data>();
+
+        // Claim all due schedules at once so that a slow backup cannot cause a schedule to run twice
+        let records: Vec<ScheduleRecord> = match sqlx::query_as(
+            "UPDATE backups__schedules SET next_run_at = NOW() + make_interval(secs => interval_secs), last_run_at = NOW() WHERE next_run_at <= NOW() RETURNING guild_id, options, keep_last, keep_weekly",
+        )
+        .fetch_all(&data.pool)
+        .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("Failed to fetch due backup schedules: {}", e);
+                continue;
+            }
+        };
+
+        for rec in records {
+            if let Err(e) = run_schedule(&data, &rec).await {
+                log::error!(
+                    "Failed to run backup schedule of guild {}: {}",
+                    rec.guild_id,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn run_schedule(data: &Data, rec: &ScheduleRecord) -> Result<(), crate::Error> {
+    let guild_id = rec.guild_id.parse::<GuildId>()?;
+
+    // Pruning first may free up enough space for the new backup
+    apply_retention(data, guild_id, rec.keep_last, rec.keep_weekly).await?;
+
+    let usage = backupusage::guild_usage(data, guild_id).await?;
+
+    if usage.is_over_quota() {
+        return Err(format!(
+            "Skipping scheduled backup as the storage quota is used up ({})",
+            usage.summary()
+        )
+        .into());
+    }
+
+    let backup_id = jobserver::spawn::spawn_task(
+        &data.reqwest,
+        &jobserver::Spawn {
+            name: "guild_create_backup".to_string(),
+            data: rec.options.clone(),
+            create: true,
+            execute: true,
+            id: None,
+            guild_id: guild_id.to_string(),
+        },
+        &CONFIG.base_ports.jobserver_base_addr,
+        CONFIG.base_ports.jobserver,
+    )
+    .await?
+    .id;
+
+    sqlx::query(
+        "INSERT INTO backups__job_metadata (job_id, guild_id, scheduled) VALUES ($1, $2, true)",
+    )
+    .bind(backup_id.parse::<Uuid>()?)
+    .bind(guild_id.to_string())
+    .execute(&data.pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the IDs of the backups to keep given their creation times (newest first)
+///
+/// The newest `keep_last` backups are kept, along with the newest backup of each of the last `keep_weekly` weeks.
+/// Pinned backups are excluded before this point and so do not count towards `keep_last`
+fn backups_to_keep(
+    backups: &[(Uuid, DateTime<Utc>)],
+    keep_last: i32,
+    keep_weekly: i32,
+    now: DateTime<Utc>,
+) -> HashSet<Uuid> {
+    let mut keep = HashSet::new();
+
+    for (id, _) in backups.iter().take(keep_last.max(0) as usize) {
+        keep.insert(*id);
+    }
+
+    let mut weeks_seen = HashSet::new();
+    for (id, created_at) in backups {
+        let week = (now - *created_at).num_weeks();
+
+        if week >= keep_weekly as i64 {
+            continue;
+        }
+
+        if weeks_seen.insert(week) {
+            keep.insert(*id);
+        }
+    }
+
+    keep
+}
+
+/// Deletes scheduled backups of a guild which fall outside of its retention policy
+///
+/// Manually created, pinned and still running backups are never deleted
+async fn apply_retention(
+    data: &Data,
+    guild_id: GuildId,
+    keep_last: i32,
+    keep_weekly: i32,
+) -> Result<(), crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct MetadataRecord {
+        job_id: Uuid,
+    }
+
+    // Metadata of backups whose job is already gone (e.g. removed by the jobserver) would otherwise never be cleaned up
+    let orphaned: Vec<MetadataRecord> = sqlx::query_as(
+        "SELECT job_id FROM backups__job_metadata WHERE guild_id = $1 AND job_id NOT IN (SELECT id FROM jobs WHERE guild_id = $1)",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(&data.pool)
+    .await?;
+
+    for rec in orphaned {
+        if let Err(e) = backupfile::delete_metadata(data, guild_id, rec.job_id).await {
+            log::error!(
+                "Failed to delete metadata of missing backup {}: {}",
+                rec.job_id,
+                e
+            );
+        }
+    }
+
+    let scheduled: Vec<MetadataRecord> = sqlx::query_as(
+        "SELECT job_id FROM backups__job_metadata WHERE guild_id = $1 AND scheduled = true AND pinned = false",
+    )
+    .bind(guild_id.to_string())
+    .fetch_all(&data.pool)
+    .await?;
+
+    let mut completed = Vec::new();
+    let mut failed = Vec::new();
+    for rec in scheduled {
+        let job = match jobserver::Job::from_id(rec.job_id, &data.pool).await {
+            Ok(job) => job,
+            Err(e) => {
+                log::warn!("Failed to get scheduled backup {}: {}", rec.job_id, e);
+                continue;
+            }
+        };
+
+        match job.state.as_str() {
+            "completed" => completed.push(job),
+            "failed" => failed.push(job),
+            _ => {}
+        }
+    }
+
+    completed.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    let keep = backups_to_keep(
+        &completed
+            .iter()
+            .map(|j| (j.id, j.created_at))
+            .collect::<Vec<_>>(),
+        keep_last,
+        keep_weekly,
+        Utc::now(),
+    );
+
+    for job in completed
+        .into_iter()
+        .filter(|j| !keep.contains(&j.id))
+        .chain(failed)
+    {
+        if let Err(e) = job.delete(&data.pool, &data.object_store).await {
+            log::error!("Failed to prune scheduled backup {}: {}", job.id, e);
+            continue;
+        }
+
+        if let Err(e) = backupfile::delete_metadata(data, guild_id, job.id).await {
+            log::error!(
+                "Failed to delete metadata of pruned backup {}: {}",
+                job.id,
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backups(now: DateTime<Utc>, ages_in_days: &[i64]) -> Vec<(Uuid, DateTime<Utc>)> {
+        ages_in_days
+            .iter()
+            .map(|days| (Uuid::new_v4(), now - chrono::Duration::days(*days)))
+            .collect()
+    }
+
+    #[test]
+    fn test_keep_last() {
+        let now = Utc::now();
+        let backups = backups(now, &[0, 1, 2, 3]);
+
+        let keep = backups_to_keep(&backups, 2, 0, now);
+        assert_eq!(keep, HashSet::from([backups[0].0, backups[1].0]));
+    }
+
+    #[test]
+    fn test_keep_weekly() {
+        let now = Utc::now();
+        // Two backups in each of the last three weeks, newest first
+        let backups = backups(now, &[1, 3, 8, 10, 15, 17]);
+
+        let keep = backups_to_keep(&backups, 0, 2, now);
+        assert_eq!(keep, HashSet::from([backups[0].0, backups[2].0]));
+    }
+
+    #[test]
+    fn test_keep_last_and_weekly_overlap() {
+        let now = Utc::now();
+        let backups = backups(now, &[1, 3, 8]);
+
+        let keep = backups_to_keep(&backups, 2, 2, now);
+        assert_eq!(keep.len(), 3);
+    }
+
+    #[test]
+    fn test_keep_nothing() {
+        let now = Utc::now();
+        let backups = backups(now, &[0, 1]);
+
+        assert!(backups_to_keep(&backups, 0, 0, now).is_empty());
+        assert!(backups_to_keep(&backups, -1, -1, now).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use serenity::all::{ChannelId, CreateEmbed, CreateMessage, GuildId};
+use silverpelt::data::Data;
+use silverpelt::lockdowns::LockdownData;
+use std::time::Duration;
+
+use crate::bot::sandwich_config;
+use crate::botlib::lockdown_snapshots::{self, ManagedScope, SnapshotKind};
+use crate::botlib::text::truncate;
+
+/// How often guilds with periodic verification enabled are checked
+const VERIFY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Stored as the last report while the lockdown state is stale so that it is only alerted once
+const STALE_REPORT: &str = "stale";
+
+#[derive(sqlx::FromRow)]
+struct AutoverifyRecord {
+    guild_id: String,
+    alert_channel: String,
+    auto_repair: bool,
+    last_report: Option<String>,
+}
+
+/// Periodically compares active lockdowns against the current guild permissions
+pub async fn lockdown_verifier(ctx: serenity::all::Context) {
+    let mut interval = tokio::time::interval(VERIFY_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let data = ctx.data::<Data>();
+
+        let records: Vec<AutoverifyRecord> = match sqlx::query_as(
+            "SELECT guild_id, alert_channel, auto_repair, last_report FROM lockdown__autoverify",
+        )
+        .fetch_all(&data.pool)
+        .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("Failed to fetch lockdown autoverify settings: {}", e);
+                continue;
+            }
+        };
+
+        for rec in records {
+            if let Err(e) = verify_guild(&ctx, &data, &rec).await {
+                log::error!(
+                    "Failed to verify lockdowns of guild {}: {}",
+                    rec.guild_id,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn verify_guild(
+    ctx: &serenity::all::Context,
+    data: &Data,
+    rec: &AutoverifyRecord,
+) -> Result<(), crate::Error> {
+    let guild_id = rec.guild_id.parse::<GuildId>()?;
+    let alert_channel = rec.alert_channel.parse::<ChannelId>()?;
+
+    let lockdowns = lockdowns::LockdownSet::guild(
+        guild_id,
+        LockdownData::new(
+            ctx.cache.clone(),
+            ctx.http.clone(),
+            data.pool.clone(),
+            data.reqwest.clone(),
+            sandwich_config(),
+        ),
+    )
+    .await
+    .map_err(|e| format!("Error while fetching lockdown set: {}", e))?;
+
+    if lockdowns.lockdowns().is_empty() {
+        return Ok(());
+    }
+
+    let Some(expected) =
+        lockdown_snapshots::load(&data.pool, guild_id, SnapshotKind::Expected).await?
+    else {
+        return Ok(());
+    };
+
+    // A lockdown applied after the snapshot is not part of it, so its overwrites would look like drift and
+    // repairing would lift it. Alert once and leave the guild alone until the state is accepted again
+    if expected.is_stale(lockdowns.lockdowns()) {
+        if rec.last_report.as_deref() == Some(STALE_REPORT) {
+            return Ok(());
+        }
+
+        send_alert(
+            ctx,
+            alert_channel,
+            "A lockdown was applied after AntiRaid last recorded the lockdown state, so it cannot be verified and nothing has been repaired.\n\nUse `/lockdowns verify` and **Accept** the current permissions to resume verification.".to_string(),
+        )
+        .await?;
+
+        sqlx::query("UPDATE lockdown__autoverify SET last_report = $1 WHERE guild_id = $2")
+            .bind(STALE_REPORT)
+            .bind(guild_id.to_string())
+            .execute(&data.pool)
+            .await?;
+
+        return Ok(());
+    }
+
+    let scope = ManagedScope::from_lockdowns(lockdowns.lockdowns(), expected.created_at).await?;
+    let actual = lockdown_snapshots::capture(&ctx.http, guild_id).await?;
+    let drifts = lockdown_snapshots::diff(&expected.snapshot, &actual, &scope);
+
+    if drifts.is_empty() {
+        if rec.last_report.is_some() {
+            sqlx::query("UPDATE lockdown__autoverify SET last_report = NULL WHERE guild_id = $1")
+                .bind(guild_id.to_string())
+                .execute(&data.pool)
+                .await?;
+        }
+
+        return Ok(());
+    }
+
+    let report = drifts
+        .iter()
+        .map(|d| format!("- {}", d))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Don't alert repeatedly for the same drift. With auto repair on, repairs that failed
+    // last time are retried silently
+    let already_reported = rec.last_report.as_deref() == Some(report.as_str());
+
+    if already_reported && !rec.auto_repair {
+        return Ok(());
+    }
+
+    let mut desc = report.clone();
+
+    if rec.auto_repair {
+        let total = drifts.len();
+        let failed = lockdown_snapshots::repair(
+            &ctx.http,
+            guild_id,
+            drifts,
+            "Periodic lockdown verification repair",
+        )
+        .await;
+
+        if already_reported {
+            return Ok(());
+        }
+
+        desc.push_str(&format!(
+            "\n\n:white_check_mark: Repaired {}/{} differences",
+            total - failed.len(),
+            total
+        ));
+
+        for (drift, err) in failed.iter() {
+            desc.push_str(&format!("\n:x: {}: {}", drift, err));
+        }
+    } else {
+        desc.push_str("\n\nUse `/lockdowns verify` to repair or accept these differences.");
+    }
+
+    send_alert(ctx, alert_channel, desc).await?;
+
+    sqlx::query("UPDATE lockdown__autoverify SET last_report = $1 WHERE guild_id = $2")
+        .bind(report)
+        .bind(guild_id.to_string())
+        .execute(&data.pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn send_alert(
+    ctx: &serenity::all::Context,
+    alert_channel: ChannelId,
+    desc: String,
+) -> Result<(), crate::Error> {
+    alert_channel
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(
+                CreateEmbed::new()
+                    .title("Lockdown Drift Detected")
+                    .description(truncate(&desc, 4000))
+                    .color(serenity::all::Colour::RED),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+pub mod backup_checksums;
+pub mod backup_scheduler;
+pub mod lockdown_verifier;
+pub mod restore_copies;
+pub mod template_error_alerts;
@@ -0,0 +1,82 @@
+use silverpelt::data::Data;
+use silverpelt::objectstore::guild_bucket;
+use sqlx::types::uuid::Uuid;
+use std::time::Duration;
+
+/// How often copied backups are checked for whether their restore has finished
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Copies whose restore job cannot be found are removed once they are this old
+const ORPHAN_HOURS: i64 = 24;
+
+#[derive(sqlx::FromRow)]
+struct CopyRecord {
+    restore_job_id: Uuid,
+    guild_id: String,
+    path: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Removes backups copied from another server for a restore once the restore job has completed or failed
+pub async fn restore_copies(ctx: serenity::all::Context) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let data = ctx.data::<Data>();
+
+        let copies: Vec<CopyRecord> = match sqlx::query_as(
+            "SELECT restore_job_id, guild_id, path, created_at FROM backups__restore_copies",
+        )
+        .fetch_all(&data.pool)
+        .await
+        {
+            Ok(copies) => copies,
+            Err(e) => {
+                log::error!("Failed to fetch copied backups: {}", e);
+                continue;
+            }
+        };
+
+        for rec in copies {
+            if let Err(e) = remove_if_done(&data, &rec).await {
+                log::error!(
+                    "Failed to remove copied backup {} of guild {}: {}",
+                    rec.path,
+                    rec.guild_id,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn remove_if_done(data: &Data, rec: &CopyRecord) -> Result<(), crate::Error> {
+    let done = match jobserver::Job::from_id(rec.restore_job_id, &data.pool).await {
+        Ok(job) => matches!(job.state.as_str(), "completed" | "failed"),
+        Err(e) => {
+            log::warn!("Failed to get restore job {}: {}", rec.restore_job_id, e);
+            rec.created_at < chrono::Utc::now() - chrono::Duration::hours(ORPHAN_HOURS)
+        }
+    };
+
+    if !done {
+        return Ok(());
+    }
+
+    data.object_store
+        .delete(
+            &guild_bucket(rec.guild_id.parse::<serenity::all::GuildId>()?),
+            &rec.path,
+        )
+        .await
+        .map_err(|e| format!("Failed to delete copied backup: {}", e))?;
+
+    sqlx::query("DELETE FROM backups__restore_copies WHERE restore_job_id = $1")
+        .bind(rec.restore_job_id)
+        .execute(&data.pool)
+        .await?;
+
+    Ok(())
+}
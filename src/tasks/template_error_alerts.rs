@@ -0,0 +1,149 @@
+use serenity::all::{ChannelId, CreateEmbed, CreateMessage, GuildId};
+use silverpelt::data::Data;
+use std::time::Duration;
+
+use crate::botlib::text::truncate;
+use crate::config::CONFIG;
+
+/// How often template error rates are checked
+const ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(sqlx::FromRow)]
+struct ErrorRateRecord {
+    guild_id: String,
+    template_name: String,
+    count: i64,
+    error_channel: Option<String>,
+}
+
+/// Sends a single summarised alert to a template's error channel once its error rate crosses the threshold
+pub async fn template_error_alerts(ctx: serenity::all::Context) {
+    let mut interval = tokio::time::interval(ALERT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let data = ctx.data::<Data>();
+
+        if let Err(e) = sqlx::query(
+            "DELETE FROM templates__errors WHERE created_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(CONFIG.template_errors.retention_days as i32)
+        .execute(&data.pool)
+        .await
+        {
+            log::error!("Failed to prune old template errors: {}", e);
+        }
+
+        // Templates which already got an alert in the current window are skipped
+        let records: Vec<ErrorRateRecord> = match sqlx::query_as(
+            "SELECT e.guild_id, e.template_name, COUNT(*) AS count, t.error_channel FROM templates__errors e
+            JOIN guild_templates t ON t.guild_id = e.guild_id AND t.name = e.template_name
+            LEFT JOIN templates__error_alerts a ON a.guild_id = e.guild_id AND a.template_name = e.template_name
+            WHERE e.created_at > NOW() - make_interval(mins => $1)
+            AND (a.last_alert_at IS NULL OR a.last_alert_at < NOW() - make_interval(mins => $1))
+            GROUP BY e.guild_id, e.template_name, t.error_channel
+            HAVING COUNT(*) >= $2",
+        )
+        .bind(CONFIG.template_errors.alert_window_mins as i32)
+        .bind(CONFIG.template_errors.alert_threshold as i64)
+        .fetch_all(&data.pool)
+        .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("Failed to fetch template error rates: {}", e);
+                continue;
+            }
+        };
+
+        for rec in records {
+            if let Err(e) = send_alert(&ctx, &data, &rec).await {
+                log::error!(
+                    "Failed to send error alert for template {} of guild {}: {}",
+                    rec.template_name,
+                    rec.guild_id,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn send_alert(
+    ctx: &serenity::all::Context,
+    data: &Data,
+    rec: &ErrorRateRecord,
+) -> Result<(), crate::Error> {
+    #[derive(sqlx::FromRow)]
+    struct ClaimRecord {
+        #[allow(dead_code)]
+        guild_id: String,
+    }
+
+    // Claim the alert first so that a failing channel does not cause an alert every minute. The claim only
+    // succeeds if no alert was sent in the current window, so each alert is sent once
+    let claim: Option<ClaimRecord> = sqlx::query_as(
+        "INSERT INTO templates__error_alerts (guild_id, template_name, last_alert_at) VALUES ($1, $2, NOW())
+        ON CONFLICT (guild_id, template_name) DO UPDATE SET last_alert_at = NOW()
+        WHERE templates__error_alerts.last_alert_at < NOW() - make_interval(mins => $3)
+        RETURNING guild_id",
+    )
+    .bind(&rec.guild_id)
+    .bind(&rec.template_name)
+    .bind(CONFIG.template_errors.alert_window_mins as i32)
+    .fetch_optional(&data.pool)
+    .await?;
+
+    if claim.is_none() {
+        return Ok(());
+    }
+
+    let Some(ref error_channel) = rec.error_channel else {
+        return Ok(());
+    };
+
+    let guild_id = rec.guild_id.parse::<GuildId>()?;
+    let channel_id = error_channel.parse::<ChannelId>()?;
+
+    let recent =
+        crate::botlib::templateerrors::list(&data.pool, guild_id, &rec.template_name, 1, 0).await?;
+
+    let mut desc = format!(
+        "`{}` raised **{}** errors in the last {} minutes.",
+        rec.template_name.replace('`', "\\`"),
+        rec.count,
+        CONFIG.template_errors.alert_window_mins
+    );
+
+    if let Some(latest) = recent.first() {
+        let mut error = latest.error.clone();
+        error = truncate(&error, 1500);
+
+        desc += &format!(
+            "\n\n**Latest error** ({}, <t:{}:R>):\n```\n{}\n```",
+            latest.event_type,
+            latest.created_at.timestamp(),
+            error.replace("```", "`\u{200b}``")
+        );
+    }
+
+    desc += &format!(
+        "\nUse `/templates errors {}` to see all errors.",
+        rec.template_name
+    );
+
+    channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(
+                CreateEmbed::new()
+                    .title(crate::botlib::templateerrors::ALERT_TITLE)
+                    .description(desc)
+                    .color(serenity::all::Colour::RED),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}